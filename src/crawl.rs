@@ -0,0 +1,144 @@
+use std::{future::Future, pin::Pin};
+
+use futures_util::future::try_join_all;
+use log::{debug, warn};
+use reqwest::Client;
+use tokio::sync::Semaphore;
+use url::Url;
+
+use crate::utils::{encode_segment, parse_autoindex, DirTree};
+
+/// Build the dir tree by following the directory links of the server's autoindex pages, at most
+/// `max_depth` levels below `url`.
+///
+/// Sibling directories are listed concurrently, with at most `concurrency` listing requests in
+/// flight. Files get no size or time, those are fetched when they are opened. The directories at
+/// `max_depth` are added empty.
+pub async fn crawl(
+    client: &Client,
+    url: &Url,
+    max_depth: usize,
+    concurrency: usize,
+) -> Result<DirTree, reqwest::Error> {
+    let mut root = DirTree {
+        name: "/".to_string(),
+        ..Default::default()
+    };
+    let listings = Semaphore::new(concurrency.max(1));
+    crawl_dir(client, url.clone(), &mut root, max_depth, &listings).await?;
+    Ok(root)
+}
+
+fn crawl_dir<'a>(
+    client: &'a Client,
+    url: Url,
+    dir: &'a mut DirTree,
+    depth: usize,
+    listings: &'a Semaphore,
+) -> Pin<Box<dyn Future<Output = Result<(), reqwest::Error>> + 'a>> {
+    Box::pin(async move {
+        let html = {
+            // Only held for the request, a directory waiting on its children must not starve them.
+            let _permit = listings.acquire().await.expect("semaphore is never closed");
+            debug!("crawl: listing {url}");
+            client
+                .get(url.clone())
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        };
+        let mut children = Vec::new();
+        let mut files = Vec::new();
+        for entry in parse_autoindex(&html) {
+            // Encoded slashes decode to names no tree can hold.
+            if entry.name.contains(['/', '\\']) {
+                warn!("crawl: skipping {:?} in {url}", entry.name);
+                continue;
+            }
+            if !entry.is_dir {
+                files.push(DirTree {
+                    name: entry.name,
+                    ..Default::default()
+                });
+                continue;
+            }
+            let child = DirTree {
+                name: format!("{}/", entry.name),
+                ..Default::default()
+            };
+            let segment = encode_segment(&entry.name);
+            let child_url = url.join(&format!("{segment}/")).unwrap();
+            if depth == 0 {
+                warn!("crawl: not listing {child_url}, --max-depth reached");
+            }
+            children.push((child, child_url));
+        }
+        if depth > 0 {
+            try_join_all(children.iter_mut().map(|(child, child_url)| {
+                crawl_dir(client, child_url.clone(), child, depth - 1, listings)
+            }))
+            .await?;
+        }
+        dir.children
+            .extend(children.into_iter().map(|(child, _)| child));
+        dir.children.extend(files);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    /// Serve an autoindex page per directory, `/a/b/` is below the depth the test crawls to.
+    fn serve_listings() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 4096];
+                let len = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..len]).to_string();
+                let body = match request.split(' ').nth(1) {
+                    Some("/") => {
+                        r#"<a href="../">../</a><a href="a/">a/</a><a href="top%20level.txt">top level.txt</a>"#
+                    }
+                    Some("/a/") => r#"<a href="b/">b/</a><a href="mid.txt">mid.txt</a>"#,
+                    _ => r#"<a href="deep.txt">deep.txt</a>"#,
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_crawl() {
+        let url = serve_listings();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let tree = runtime.block_on(crawl(&Client::new(), &url, 1, 2)).unwrap();
+        let names = |dir: &DirTree| {
+            dir.children
+                .iter()
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&tree), ["a/", "top level.txt"]);
+        let a = &tree.children[0];
+        assert_eq!(names(a), ["b/", "mid.txt"]);
+        // Past `max_depth`, not listed.
+        assert!(a.children[0].children.is_empty());
+        assert!(!tree.children[1].is_folder());
+    }
+}
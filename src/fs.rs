@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod entry;
 pub mod handler;
 pub mod metadata;
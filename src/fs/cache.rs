@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// Name of the file listing the complete bodies of a persistent cache.
+const INDEX_FILE: &str = "index.json";
+
+/// What identifies a cached body.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheKey {
+    /// The full URL.
+    #[default]
+    Url,
+    /// The URL without its query, for versioned assets whose content doesn't depend on it.
+    UrlNoQuery,
+    /// The URL without its query together with the `ETag` the server sends for it.
+    Etag,
+}
+
+impl FromStr for CacheKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "url" => Ok(CacheKey::Url),
+            "url-no-query" => Ok(CacheKey::UrlNoQuery),
+            "etag" => Ok(CacheKey::Etag),
+            _ => Err(format!("unknown cache key `{s}`")),
+        }
+    }
+}
+
+/// A complete body as the index of a persistent cache records it, with what is needed to
+/// revalidate it on a later mount.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedBody {
+    /// The cache key, the URL unless `--cache-key` says otherwise.
+    pub key: String,
+    pub len: u64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Downloaded bodies spilled to files in a directory, keyed by a hash of their cache key.
+///
+/// With `persist` the complete bodies outlive the mount. `index.json` next to them maps each file
+/// name to its `CachedBody`, and a later mount revalidates them with the server before use.
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    key: CacheKey,
+    /// Whether complete bodies are served again, `--no-cache` only writes them.
+    reuse: bool,
+    persist: bool,
+    /// Files written during this session, with their length once the download completed.
+    files: Mutex<HashMap<PathBuf, Option<u64>>>,
+    /// Numbers the part files so downloads of the same key never share one.
+    parts: AtomicU64,
+    /// Complete bodies by file name, loaded from the index and kept up to date with `persist`.
+    index: Mutex<HashMap<String, CachedBody>>,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, key: CacheKey, reuse: bool, persist: bool) -> Self {
+        let index = match persist {
+            true => load_index(&dir),
+            false => HashMap::new(),
+        };
+        Self {
+            dir,
+            key,
+            reuse,
+            persist,
+            files: Mutex::new(HashMap::new()),
+            parts: AtomicU64::new(0),
+            index: Mutex::new(index),
+        }
+    }
+
+    /// Whether keys can only be made once the server has sent an `ETag`.
+    pub fn needs_etag(&self) -> bool {
+        self.key == CacheKey::Etag
+    }
+
+    /// The cache key of `url`, `None` when the strategy needs an `etag` and there is none.
+    pub fn key_for(&self, url: &Url, etag: Option<&str>) -> Option<String> {
+        let mut base = url.clone();
+        base.set_query(None);
+        base.set_fragment(None);
+        match self.key {
+            CacheKey::Url => Some(url.to_string()),
+            CacheKey::UrlNoQuery => Some(base.to_string()),
+            CacheKey::Etag => etag.map(|etag| format!("{base} {etag}")),
+        }
+    }
+
+    pub fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(file_name(key))
+    }
+
+    /// Reopen a body that was fully downloaded earlier in this session.
+    pub fn open_complete(&self, key: &str) -> Option<(File, u64)> {
+        if !self.reuse {
+            return None;
+        }
+        let path = self.path_for(key);
+        let len = (*self.files.lock().unwrap().get(&path)?)?;
+        match File::open(&path) {
+            Ok(file) => Some((file, len)),
+            Err(e) => {
+                warn!("cache: failed to reopen {:?}: {e}", path);
+                None
+            }
+        }
+    }
+
+    /// A body an earlier mount completed, to be revalidated with the server before it is served.
+    pub fn stale(&self, key: &str) -> Option<CachedBody> {
+        if !self.reuse || self.files.lock().unwrap().contains_key(&self.path_for(key)) {
+            return None;
+        }
+        self.index.lock().unwrap().get(&file_name(key)).cloned()
+    }
+
+    /// The server confirmed the `stale` body of `key` is current, serve it like one downloaded now.
+    pub fn revalidated(&self, key: &str) {
+        let Some(len) = self
+            .index
+            .lock()
+            .unwrap()
+            .get(&file_name(key))
+            .map(|b| b.len)
+        else {
+            return;
+        };
+        self.files
+            .lock()
+            .unwrap()
+            .insert(self.path_for(key), Some(len));
+    }
+
+    /// Create a part file for a download of `key`, `mark_complete` moves it in place once the body
+    /// is complete. Each download gets its own, so concurrent ones of the same key don't mix.
+    pub fn create(&self, key: &str) -> io::Result<(File, PathBuf)> {
+        fs::create_dir_all(&self.dir)?;
+        // Whatever the index said about the old content no longer holds.
+        self.index.lock().unwrap().remove(&file_name(key));
+        let part = self.parts.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{}.{part}.part", file_name(key)));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        self.files.lock().unwrap().insert(path.clone(), None);
+        Ok((file, path))
+    }
+
+    /// Move the `part` file of a complete download to where `open_complete` finds `body`.
+    pub fn mark_complete(&self, part: &Path, body: CachedBody) {
+        let path = self.path_for(&body.key);
+        let mut files = self.files.lock().unwrap();
+        if let Err(e) = fs::rename(part, &path) {
+            warn!("cache: failed to move {:?} to {:?}: {e}", part, path);
+            return;
+        }
+        files.remove(part);
+        files.insert(path, Some(body.len));
+        drop(files);
+        if self.persist {
+            let mut index = self.index.lock().unwrap();
+            index.insert(file_name(&body.key), body);
+            self.save_index(&index);
+        }
+    }
+
+    /// Remove every file written during this session, or with `persist` only the incomplete ones.
+    pub fn clear(&self) {
+        for (path, len) in self.files.lock().unwrap().drain() {
+            if self.persist && len.is_some() {
+                continue;
+            }
+            debug!("cache: removing {:?}", path);
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("cache: failed to remove {:?}: {e}", path);
+            }
+        }
+    }
+
+    fn save_index(&self, index: &HashMap<String, CachedBody>) {
+        let path = self.dir.join(INDEX_FILE);
+        // Written aside and renamed so a crash never leaves half an index.
+        let tmp = path.with_extension("json.tmp");
+        let saved = serde_json::to_vec_pretty(index)
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(&tmp, json))
+            .and_then(|()| fs::rename(&tmp, &path));
+        if let Err(e) = saved {
+            warn!("cache: failed to save {:?}: {e}", path);
+        }
+    }
+}
+
+fn file_name(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// The entries of the index in `dir` whose file is still there with the recorded length.
+fn load_index(dir: &Path) -> HashMap<String, CachedBody> {
+    let path = dir.join(INDEX_FILE);
+    let index: HashMap<String, CachedBody> = match fs::read(&path) {
+        Ok(json) => match serde_json::from_slice(&json) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("cache: ignoring unreadable {:?}: {e}", path);
+                return HashMap::new();
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            warn!("cache: failed to read {:?}: {e}", path);
+            return HashMap::new();
+        }
+    };
+    index
+        .into_iter()
+        .filter(|(name, body)| {
+            let len = fs::metadata(dir.join(name)).map(|m| m.len()).ok();
+            if len != Some(body.len) {
+                debug!("cache: dropping {name} from the index, the file is gone or changed");
+            }
+            len == Some(body.len)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_key_for() {
+        let url = Url::parse("http://example.com/app.js?v=2#top").unwrap();
+        let cache = |key| DiskCache::new(PathBuf::new(), key, true, false);
+        assert_eq!(
+            cache(CacheKey::Url).key_for(&url, None).as_deref(),
+            Some("http://example.com/app.js?v=2#top")
+        );
+        assert_eq!(
+            cache(CacheKey::UrlNoQuery).key_for(&url, None).as_deref(),
+            Some("http://example.com/app.js")
+        );
+        assert_eq!(cache(CacheKey::Etag).key_for(&url, None), None);
+        assert_eq!(
+            cache(CacheKey::Etag)
+                .key_for(&url, Some("\"abc\""))
+                .as_deref(),
+            Some("http://example.com/app.js \"abc\"")
+        );
+    }
+
+    #[test]
+    fn test_no_reuse() {
+        let dir = std::env::temp_dir().join(format!("http-fs-cache-{}", std::process::id()));
+        for reuse in [true, false] {
+            let cache = DiskCache::new(dir.clone(), CacheKey::Url, reuse, false);
+            let (_, part) = cache.create("key").unwrap();
+            cache.mark_complete(
+                &part,
+                CachedBody {
+                    key: "key".to_string(),
+                    ..Default::default()
+                },
+            );
+            assert_eq!(cache.open_complete("key").is_some(), reuse);
+            cache.clear();
+        }
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_persist() {
+        let dir = std::env::temp_dir().join(format!("http-fs-persist-{}", std::process::id()));
+        let body = CachedBody {
+            key: "http://example.com/app.js".to_string(),
+            len: 2,
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        };
+        let cache = DiskCache::new(dir.clone(), CacheKey::Url, true, true);
+        let (_, partial) = cache.create("partial").unwrap();
+        let (mut file, part) = cache.create(&body.key).unwrap();
+        file.write_all(b"ok").unwrap();
+        drop(file);
+        cache.mark_complete(&part, body.clone());
+        assert!(!part.exists());
+        // Bodies of this session need no revalidation.
+        assert_eq!(cache.stale(&body.key), None);
+        cache.clear();
+        assert!(!partial.exists());
+
+        let cache = DiskCache::new(dir.clone(), CacheKey::Url, true, true);
+        assert_eq!(cache.stale(&body.key), Some(body.clone()));
+        assert!(cache.open_complete(&body.key).is_none());
+        cache.revalidated(&body.key);
+        assert_eq!(cache.open_complete(&body.key).map(|(_, len)| len), Some(2));
+
+        // A file changed behind the cache's back is dropped from the index.
+        fs::write(cache.path_for(&body.key), b"changed").unwrap();
+        let cache = DiskCache::new(dir.clone(), CacheKey::Url, true, true);
+        assert_eq!(cache.stale(&body.key), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_downloads() {
+        let dir = std::env::temp_dir().join(format!("http-fs-parts-{}", std::process::id()));
+        let cache = DiskCache::new(dir.clone(), CacheKey::Url, true, false);
+        let (mut first, first_part) = cache.create("key").unwrap();
+        let (mut second, second_part) = cache.create("key").unwrap();
+        assert_ne!(first_part, second_part);
+        first.write_all(b"first").unwrap();
+        second.write_all(b"second").unwrap();
+        drop((first, second));
+        cache.mark_complete(
+            &second_part,
+            CachedBody {
+                key: "key".to_string(),
+                len: 6,
+                ..Default::default()
+            },
+        );
+        assert_eq!(fs::read(cache.path_for("key")).unwrap(), b"second");
+        assert_eq!(fs::read(&first_part).unwrap(), b"first");
+        cache.clear();
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -1,8 +1,9 @@
-use crate::fs::metadata::Stat;
+use crate::fs::metadata::{AltStream, Stat};
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock},
+    sync::{Arc, RwLock, Weak},
 };
+use url::Url;
 
 use super::EntryName;
 
@@ -90,7 +91,33 @@ impl FileEntry {
 pub struct HttpFileEntry {
     pub stat: RwLock<Stat>,
     pub download_pending: RwLock<bool>,
-    data_cache: RwLock<Option<Vec<u8>>>,
+    data_cache: RwLock<Option<Arc<[u8]>>>,
+    /// Stream of the most recent open, shared with later opens when streams are deduplicated.
+    pub shared_stream: RwLock<Option<Arc<RwLock<AltStream>>>>,
+    /// Where the content is fetched from when the entry's path doesn't map to it, e.g. after renaming.
+    pub url: RwLock<Option<Url>>,
+    /// Complete download kept for the whole mount and served to every open, see `--preopen`.
+    pub pinned: RwLock<Option<Arc<RwLock<AltStream>>>>,
+    /// Size declared by the dir tree, reported before the file is downloaded.
+    pub size: RwLock<Option<u64>>,
+    /// Size settled by the latest download from the `--content-length-from` sources.
+    pub resolved_size: RwLock<Option<u64>>,
+    /// The times came from the dir tree or `set_file_time`, downloads don't replace them with
+    /// `Last-Modified`.
+    pub times_pinned: RwLock<bool>,
+    /// Hex SHA-256 declared by the dir tree, downloads that don't match it are unreadable.
+    pub sha256: Option<String>,
+    /// Stream of the most recent download for as long as a handle keeps it open, see `:progress`.
+    pub latest_stream: RwLock<Weak<RwLock<AltStream>>>,
+    /// Download started by `--prefetch` while a sibling was opened, handed to the next open.
+    pub prefetched: RwLock<Option<Arc<RwLock<AltStream>>>>,
+    /// ETag of the body kept in `data_cache`, the next open sends it as `If-None-Match`.
+    etag: RwLock<Option<String>>,
+    /// Why the latest download failed, reads that wait for it fail instead. Cleared by the next one.
+    pub download_error: RwLock<Option<String>>,
+    /// Extension of the content type `--append-ext-from-type` lists the file with. The entry keeps
+    /// its own name in the tree.
+    pub shown_ext: RwLock<Option<&'static str>>,
 }
 
 unsafe impl Send for HttpFileEntry {}
@@ -102,8 +129,28 @@ impl HttpFileEntry {
             stat: RwLock::new(stat),
             download_pending: RwLock::new(true),
             data_cache: RwLock::new(None),
+            shared_stream: RwLock::new(None),
+            url: RwLock::new(None),
+            pinned: RwLock::new(None),
+            size: RwLock::new(None),
+            resolved_size: RwLock::new(None),
+            times_pinned: RwLock::new(false),
+            sha256: None,
+            latest_stream: RwLock::new(Weak::new()),
+            prefetched: RwLock::new(None),
+            etag: RwLock::new(None),
+            download_error: RwLock::new(None),
+            shown_ext: RwLock::new(None),
         }
     }
+    /// The size settled by a download, else the declared size, else the size of the cached data.
+    pub fn size(&self) -> u64 {
+        self.resolved_size
+            .read()
+            .unwrap()
+            .or(*self.size.read().unwrap())
+            .unwrap_or_else(|| self.data_len() as u64)
+    }
     pub fn data_len(&self) -> usize {
         self.data_cache
             .read()
@@ -111,9 +158,23 @@ impl HttpFileEntry {
             .as_ref()
             .map_or(0, |data| data.len())
     }
-    pub fn get_data(&self) -> Option<Vec<u8>> {
+    pub fn get_data(&self) -> Option<Arc<[u8]>> {
         self.data_cache.read().unwrap().clone()
     }
+    /// Keep a complete download and its ETag to serve again when the server answers 304.
+    pub fn remember(&self, etag: String, data: Arc<[u8]>) {
+        *self.data_cache.write().unwrap() = Some(data);
+        *self.etag.write().unwrap() = Some(etag);
+    }
+    /// The ETag and body kept by `remember`, sharing the body rather than copying it.
+    pub fn remembered(&self) -> Option<(String, Arc<[u8]>)> {
+        Some((self.etag.read().unwrap().clone()?, self.get_data()?))
+    }
+    /// Drop the body kept by `remember`, for a file that is going away.
+    pub fn forget(&self) {
+        *self.data_cache.write().unwrap() = None;
+        *self.etag.write().unwrap() = None;
+    }
 }
 
 // The compiler incorrectly believes that its usage in a public function of the private path module is public.
@@ -121,6 +182,8 @@ impl HttpFileEntry {
 pub struct DirEntry {
     pub stat: RwLock<Stat>,
     pub children: RwLock<HashMap<EntryName, Arc<Entry>>>,
+    /// Tree path of the directory this one stands for, see `path::resolve_link`.
+    pub link: Option<String>,
 }
 
 impl DirEntry {
@@ -128,6 +191,12 @@ impl DirEntry {
         Self {
             stat: RwLock::new(stat),
             children: RwLock::new(HashMap::new()),
+            link: None,
         }
     }
+
+    pub fn with_link(mut self, link: String) -> Self {
+        self.link = Some(link);
+        self
+    }
 }
@@ -7,11 +7,19 @@ use widestring::{U16Str, U16String};
 #[derive(Debug, Eq)]
 pub struct EntryNameRef(pub U16Str);
 
+/// Fold a UTF-16 unit for the case-insensitive matching Windows applications expect. Letters
+/// outside ASCII fold too, as long as their lowercase is a single unit; surrogates stay as they are.
 fn u16_tolower(c: u16) -> u16 {
-    if c >= 'A' as u16 && c <= 'Z' as u16 {
-        c + 'a' as u16 - 'A' as u16
-    } else {
-        c
+    if c < 0x80 {
+        return (c as u8).to_ascii_lowercase() as u16;
+    }
+    let Some(ch) = char::from_u32(c as u32) else {
+        return c;
+    };
+    let mut lower = ch.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) if (l as u32) <= 0xFFFF => l as u16,
+        _ => c,
     }
 }
 
@@ -65,3 +73,19 @@ impl PartialEq for EntryName {
 }
 
 impl Eq for EntryName {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_name_ignores_case() {
+        let name = |s: &str| EntryName(U16String::from_str(s));
+        assert_eq!(name("INDEX.HTML"), name("index.html"));
+        assert_eq!(name("ÉTÉ.txt"), name("été.txt"));
+        assert_ne!(name("index.html"), name("index.htm"));
+        let mut names = std::collections::HashSet::new();
+        names.insert(name("Straße"));
+        assert!(names.contains(EntryNameRef::new(&U16String::from_str("STRAßE"))));
+    }
+}
@@ -1,4 +1,7 @@
 mod entry_handler;
 mod memfs_handler;
+mod options;
+mod stats;
 pub use entry_handler::EntryHandle;
-pub use memfs_handler::MemFsHandler;
\ No newline at end of file
+pub use memfs_handler::{http_to_status, MemFsHandler};
+pub use options::{MemFsOptions, SizeSource};
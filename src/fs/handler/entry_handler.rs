@@ -1,16 +1,19 @@
-use log::debug;
+use dokan::{FileTimeOperation, OperationResult};
+use log::{debug, warn};
 use std::{
     borrow::Borrow,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock, Weak,
     },
     time::SystemTime,
 };
 
-use crate::fs::metadata::{AltStream, Stat};
+use winapi::{shared::ntstatus::STATUS_INVALID_PARAMETER, um::winnt};
 
-use super::super::entry::{Entry, EntryNameRef};
+use crate::fs::metadata::{AltStream, Attributes, Stat};
+
+use super::super::entry::{Entry, EntryName, EntryNameRef};
 
 #[allow(unused)]
 #[derive(Debug)]
@@ -24,6 +27,13 @@ pub struct EntryHandle {
     pub ctime_enabled: AtomicBool,
     pub mtime_enabled: AtomicBool,
     pub atime_enabled: AtomicBool,
+    /// End offset of the previous read, a read starting here is sequential.
+    pub read_end: AtomicU64,
+    /// Name of the last entry `find_files` returned before the buffer filled up, the next call
+    /// continues after it.
+    pub find_resume: Mutex<Option<EntryName>>,
+    /// Written since it was last uploaded, see `--writable`.
+    pub dirty: AtomicBool,
 }
 
 // static mut INDEX: u32 = 0;
@@ -49,6 +59,9 @@ impl EntryHandle {
             ctime_enabled: AtomicBool::new(true),
             mtime_enabled: AtomicBool::new(true),
             atime_enabled: AtomicBool::new(true),
+            read_end: AtomicU64::new(0),
+            find_resume: Mutex::new(None),
+            dirty: AtomicBool::new(false),
         }
     }
 
@@ -66,6 +79,69 @@ impl EntryHandle {
         }
     }
 
+    /// Take the attributes of `set_file_attributes`, 0 leaves them unchanged. Unsupported flags
+    /// are dropped like for new entries, but a file can't be turned into a directory.
+    pub fn set_attributes(&self, attrs: u32) -> OperationResult<()> {
+        if attrs & winnt::FILE_ATTRIBUTE_DIRECTORY != 0 && !self.entry.is_dir() {
+            return Err(STATUS_INVALID_PARAMETER);
+        }
+        if attrs != 0 {
+            self.entry.stat().write().unwrap().attrs = Attributes::new(attrs);
+        }
+        Ok(())
+    }
+
+    /// Take the times of `set_file_time`. The creation time is set right away, the access and
+    /// write times are kept until `close_file` so the handle's own reads and writes don't
+    /// overwrite them. Disabling or resuming automatic updates only flips the handle's flags.
+    /// An http file keeps times set this way over the `Last-Modified` of later downloads.
+    pub fn set_times(
+        &self,
+        creation_time: FileTimeOperation,
+        last_access_time: FileTimeOperation,
+        last_write_time: FileTimeOperation,
+    ) {
+        let time = |op: FileTimeOperation, enabled: &AtomicBool| match op {
+            FileTimeOperation::SetTime(time) => Some(time),
+            FileTimeOperation::DontChange => None,
+            FileTimeOperation::DisableUpdate => {
+                enabled.store(false, Ordering::Relaxed);
+                None
+            }
+            FileTimeOperation::ResumeUpdate => {
+                enabled.store(true, Ordering::Relaxed);
+                None
+            }
+        };
+        let mut pinned = false;
+        if let Some(ctime) = time(creation_time, &self.ctime_enabled) {
+            self.entry.stat().write().unwrap().ctime = ctime;
+            pinned = true;
+        }
+        if let Some(atime) = time(last_access_time, &self.atime_enabled) {
+            *self.atime_delayed.lock().unwrap() = Some(atime);
+        }
+        if let Some(mtime) = time(last_write_time, &self.mtime_enabled) {
+            *self.mtime_delayed.lock().unwrap() = Some(mtime);
+            pinned = true;
+        }
+        if let (true, Entry::HttpFile(file)) = (pinned, self.entry.as_ref()) {
+            *file.times_pinned.write().unwrap() = true;
+        }
+    }
+
+    /// Apply the access and write times `set_times` kept, for `close_file`. They win even when
+    /// they are older.
+    pub fn commit_times(&self) {
+        let mut stat = self.entry.stat().write().unwrap();
+        if let Some(mtime) = *self.mtime_delayed.lock().unwrap() {
+            stat.mtime = mtime;
+        }
+        if let Some(atime) = *self.atime_delayed.lock().unwrap() {
+            stat.atime = atime;
+        }
+    }
+
     #[allow(unused)]
     pub fn update_mtime(&self, stat: &mut Stat, mtime: SystemTime) {
         self.update_atime(stat, mtime);
@@ -101,11 +177,20 @@ impl Drop for EntryHandle {
             let key = parent_children
                 .iter()
                 .find_map(|(k, v)| if &self.entry == v { Some(k) } else { None })
-                .unwrap()
-                .clone();
-            parent_children
-                .remove(Borrow::<EntryNameRef>::borrow(&key))
-                .unwrap();
+                .cloned();
+            if let Entry::HttpFile(file) = self.entry.as_ref() {
+                file.forget();
+            }
+            match key {
+                Some(key) => {
+                    parent_children.remove(Borrow::<EntryNameRef>::borrow(&key));
+                }
+                // Replaced or never added, there is nothing of ours to remove.
+                None => warn!(
+                    "EntryHandle::drop: [{}] entry is not in its parent",
+                    self.index
+                ),
+            }
         } else {
             // Ignore root directory.
             stat.delete_pending = false
@@ -119,6 +204,11 @@ impl Drop for EntryHandle {
             }
             stream_locked.handle_count -= 1;
             if stream_locked.delete_pending && stream_locked.handle_count == 0 {
+                // A download still running keeps the stream alive, don't wait for it to go.
+                stream_locked.release();
+                if let Entry::HttpFile(file) = self.entry.as_ref() {
+                    file.forget();
+                }
                 let key = stat
                     .alt_streams
                     .iter()
@@ -129,13 +219,33 @@ impl Drop for EntryHandle {
                             None
                         }
                     })
-                    .unwrap()
-                    .clone();
-                stat.alt_streams
-                    .remove(Borrow::<EntryNameRef>::borrow(&key))
-                    .unwrap();
+                    .cloned();
+                match key {
+                    Some(key) => {
+                        stat.alt_streams
+                            .remove(Borrow::<EntryNameRef>::borrow(&key));
+                    }
+                    // Snapshots like `:progress` and the streams of http file opens are only
+                    // held by their handles.
+                    None => debug!(
+                        "EntryHandle::drop: [{}] stream is not listed on its entry",
+                        self.index
+                    ),
+                }
                 self.update_atime(&mut stat, SystemTime::now());
             }
         }
+        drop(stat);
+        // Opening the file again has to start over instead of finding the released stream.
+        if let (Entry::HttpFile(file), Some(stream)) = (self.entry.as_ref(), alt_stream.as_ref()) {
+            let released = {
+                let stream = stream.read().unwrap();
+                stream.delete_pending && stream.handle_count == 0
+            };
+            let mut latest = file.latest_stream.write().unwrap();
+            if released && latest.as_ptr() == Arc::as_ptr(stream) {
+                *latest = Weak::new();
+            }
+        }
     }
 }
@@ -0,0 +1,168 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::header::HeaderMap;
+use url::Url;
+
+use crate::fs::cache::CacheKey;
+
+/// Where the size of an http file can come from, see `--content-length-from`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeSource {
+    /// `Content-Length` (or the `Content-Range` total) of the response the body comes from.
+    Header,
+    /// `Content-Length` of a separate HEAD request.
+    Head,
+    /// The `size` declared by the dir tree.
+    Manifest,
+    /// The number of bytes received, once the download is complete.
+    Download,
+}
+
+impl SizeSource {
+    pub const DEFAULT_ORDER: [SizeSource; 4] = [
+        SizeSource::Header,
+        SizeSource::Head,
+        SizeSource::Manifest,
+        SizeSource::Download,
+    ];
+}
+
+impl FromStr for SizeSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "header" => Ok(SizeSource::Header),
+            "head" => Ok(SizeSource::Head),
+            "manifest" => Ok(SizeSource::Manifest),
+            "download" => Ok(SizeSource::Download),
+            _ => Err(format!("unknown size source `{s}`")),
+        }
+    }
+}
+
+/// Settings for `MemFsHandler` collected from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct MemFsOptions {
+    /// Spill downloaded bytes to files in this directory instead of keeping them in memory.
+    pub cache_dir: Option<PathBuf>,
+    /// How bodies in `cache_dir` are identified.
+    pub cache_key: CacheKey,
+    /// Extra delay added to every download and read.
+    pub inject_latency: Duration,
+    /// Fraction of downloads and reads that fail on purpose, in `0.0..=1.0`.
+    pub inject_failure_rate: f64,
+    pub inject_seed: u64,
+    /// Sent with every request to the backend.
+    pub headers: HeaderMap,
+    /// `User-Agent` of every request, empty sends none.
+    pub user_agent: String,
+    /// Route every request through this HTTP, HTTPS or SOCKS5 proxy.
+    pub proxy: Option<Url>,
+    /// Ignore the proxies configured in the environment.
+    pub no_proxy: bool,
+    /// Opens of a file that is already open share its stream instead of downloading again.
+    pub deduplicate_streams: bool,
+    /// Bytes fetched ahead of a sequential read that the download hasn't reached yet, 0 disables it.
+    pub read_ahead: u64,
+    /// Opening a file missing from the dir tree fails instead of adding it from the server.
+    pub manifest_only: bool,
+    /// Give up on a request when the server takes longer than this to send headers, zero waits forever.
+    pub response_header_timeout: Duration,
+    /// Extensionless files are renamed with the extension of their content type once it is known.
+    pub append_ext_from_type: bool,
+    /// Detect the content type from the first bytes of a download instead of trusting the server.
+    pub sniff_content_type: bool,
+    /// How many times a download is retried after a connection failure or server error.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each one after it.
+    pub retry_base: Duration,
+    /// Redirects followed per request, 0 makes any redirect an error.
+    pub max_redirects: usize,
+    /// Listing a directory with more children than this logs a warning, 0 disables it.
+    pub warn_large_dir: usize,
+    /// Such listings stop after `warn_large_dir` children and end with a marker entry.
+    pub truncate_large_dir: bool,
+    /// Http files can be written, the new content is uploaded with a PUT on flush and close.
+    pub writable: bool,
+    /// `file_index` is a hash of the path instead of a per-mount counter.
+    pub stable_file_index: bool,
+    /// Longest file or stream name accepted, 0 means the NTFS limit of 255.
+    pub max_name_length: u32,
+    /// A read waiting this long without the download making progress fails, zero waits forever.
+    pub read_timeout: Duration,
+    /// How often a waiting read checks the download.
+    pub poll_interval: Duration,
+    /// Like `retries`, for the requests that only learn the size of a file.
+    pub probe_retries: u32,
+    /// Client and server errors are served as the file content instead of failing the download.
+    pub serve_error_body: bool,
+    /// Combined download rate cap in bytes per second, 0 means unlimited.
+    pub max_bps: u64,
+    /// Timestamp of the root and the synthetic directories, `None` pins the mount time.
+    pub synthetic_time: Option<SystemTime>,
+    /// Other http files of the directory downloaded in the background when one is opened, 0 disables it.
+    pub prefetch: usize,
+    /// Never serve a body from the disk cache, only write it there.
+    pub no_cache: bool,
+    /// Sources of an http file's size, the first one that knows it wins. Empty means
+    /// `SizeSource::DEFAULT_ORDER`.
+    pub content_length_from: Vec<SizeSource>,
+    /// Parallel range requests a download is split into, 0 and 1 download in one piece.
+    pub download_chunks: usize,
+    /// Path lookups create the directories they don't find instead of failing.
+    pub auto_create_dirs: bool,
+    /// Substitutions applied in order to every request url.
+    pub url_rewrites: Vec<(String, String)>,
+    /// Total size reported for the volume, 0 means 1 GiB.
+    pub volume_size: u64,
+    /// Free space reported for the volume, 0 means 512 MiB.
+    pub free_space: u64,
+    /// Name of the volume, empty means "Http FileSystem".
+    pub volume_label: String,
+    /// Serial number of the volume.
+    pub serial: u32,
+    /// Give up connecting to the server after this long, zero waits forever.
+    pub connect_timeout: Duration,
+    /// Give up on a request, body included, after this long, zero waits forever.
+    pub request_timeout: Duration,
+    /// A read-only `.stats.json` at the root serves the download counters, and `.downloads.json` the
+    /// downloads in progress.
+    pub stats_file: bool,
+    /// The volume is mounted write-protected and says so in its flags.
+    pub read_only: bool,
+    /// Complete bodies stay in `cache_dir` across mounts and are revalidated before reuse.
+    pub cache_persist: bool,
+    /// File requested for a directory url, empty means `index.html`.
+    pub index_file: String,
+    /// Longest path inside the volume accepted, 0 means `MAX_PATH`.
+    pub max_path: u32,
+    /// A read-only `.mount-info.txt` at the root describes the mount, unless `--no-mount-info`.
+    pub mount_info: bool,
+    /// Binary SID owning every entry, the local system when unset.
+    pub owner: Option<Vec<u8>>,
+    /// Everyone may only read entries, instead of authenticated users also writing.
+    pub everyone_read_only: bool,
+    /// Directory below the url the dir tree paths are relative to, empty means the url itself.
+    pub base_path: String,
+    /// Leading components removed from the mount paths before they become urls.
+    pub strip_prefix: String,
+    /// An open waits this long for the response headers and fails if the server can't serve the file, zero doesn't wait.
+    pub open_timeout: Duration,
+    /// Requests open at once, downloads, chunks, range reads and HEADs alike. The others wait for
+    /// one to end. 0 means unlimited.
+    pub connect_limit: usize,
+}
+
+impl MemFsOptions {
+    pub fn size_sources(&self) -> &[SizeSource] {
+        match self.content_length_from.is_empty() {
+            true => &SizeSource::DEFAULT_ORDER,
+            false => &self.content_length_from,
+        }
+    }
+}
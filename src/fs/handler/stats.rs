@@ -0,0 +1,76 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Download counters of the mount, served as `.stats.json` with `--stats-file`.
+#[derive(Debug, Default)]
+pub struct DownloadStats {
+    bytes_downloaded: AtomicU64,
+    active_downloads: AtomicU64,
+    completed_downloads: AtomicU64,
+    cache_hits: AtomicU64,
+}
+
+impl DownloadStats {
+    /// Count a download as active until the returned guard is dropped, whichever way it ends.
+    pub fn start(self: &Arc<Self>) -> ActiveDownload {
+        self.active_downloads.fetch_add(1, Ordering::Relaxed);
+        ActiveDownload(Arc::clone(self))
+    }
+
+    pub fn add_bytes(&self, len: usize) {
+        self.bytes_downloaded
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_completed(&self) {
+        self.completed_downloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"bytes_downloaded":{},"active_downloads":{},"completed_downloads":{},"cache_hits":{}}}"#,
+            self.bytes_downloaded.load(Ordering::Relaxed),
+            self.active_downloads.load(Ordering::Relaxed),
+            self.completed_downloads.load(Ordering::Relaxed),
+            self.cache_hits.load(Ordering::Relaxed),
+        )
+    }
+}
+
+pub struct ActiveDownload(Arc<DownloadStats>);
+
+impl Drop for ActiveDownload {
+    fn drop(&mut self) {
+        self.0.active_downloads.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_stats() {
+        let stats = Arc::new(DownloadStats::default());
+        let active = stats.start();
+        stats.add_bytes(10);
+        stats.add_bytes(5);
+        stats.add_cache_hit();
+        assert_eq!(
+            stats.to_json(),
+            r#"{"bytes_downloaded":15,"active_downloads":1,"completed_downloads":0,"cache_hits":1}"#
+        );
+        stats.add_completed();
+        drop(active);
+        assert_eq!(
+            stats.to_json(),
+            r#"{"bytes_downloaded":15,"active_downloads":0,"completed_downloads":1,"cache_hits":1}"#
+        );
+    }
+}
@@ -1,13 +1,21 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io,
+    os::windows::fs::FileExt,
     sync::{Arc, RwLock, Weak},
     time::SystemTime,
 };
 
+use url::Url;
 use winapi::um::winnt;
 
 use super::entry::{DirEntry, EntryName};
-use crate::security::SecurityDescriptor;
+use crate::{security::SecurityDescriptor, utils::Progress};
+
+/// Most bytes `AltStream::ranges` keeps from Range reads, the ranges farthest from the latest read
+/// are dropped past it.
+pub const MAX_RANGES_LEN: u64 = 64 * 1024 * 1024;
 
 #[derive(Debug, serde::Serialize)]
 pub struct AltStream {
@@ -16,6 +24,35 @@ pub struct AltStream {
     pub data: Vec<u8>,
     pub content_length: u64,
     pub ctime: SystemTime,
+    /// The body is being downloaded, not just its headers.
+    pub full_download: bool,
+    /// The server answered with `Accept-Ranges: bytes`.
+    pub accept_ranges: bool,
+    #[serde(skip)]
+    pub url: Option<Url>,
+    /// Chunks fetched with Range requests, keyed by their offset. `data` stays the contiguous prefix.
+    #[serde(skip)]
+    pub ranges: BTreeMap<u64, Vec<u8>>,
+    /// When set, the contiguous prefix lives in this file instead of `data`.
+    #[serde(skip)]
+    pub cache_file: Option<File>,
+    pub cached_len: u64,
+    /// Written through the mount, `content_length` is then the written size rather than the server's.
+    pub modified: bool,
+    /// Parts past the prefix are being downloaded in parallel into `ranges`, see `--download-chunks`.
+    pub chunked: bool,
+    /// The download reached its end, `content_length` won't change anymore.
+    pub done: bool,
+    /// The server stopped answering in time, reads of the data it didn't send fail with
+    /// `STATUS_IO_TIMEOUT` instead of waiting for it.
+    pub timed_out: bool,
+    /// The finished download doesn't match the sha256 declared in the dir tree, reads fail.
+    pub corrupt: bool,
+    /// HTTP status the server answered with, once the response headers arrived.
+    pub status: Option<u16>,
+    /// Notified as the download gets its headers and each chunk of the body.
+    #[serde(skip)]
+    pub progress: Arc<Progress>,
 }
 
 impl AltStream {
@@ -26,9 +63,206 @@ impl AltStream {
             data: Vec::new(),
             content_length: 0,
             ctime: SystemTime::now(),
+            full_download: false,
+            accept_ranges: false,
+            url: None,
+            ranges: BTreeMap::new(),
+            cache_file: None,
+            cached_len: 0,
+            modified: false,
+            chunked: false,
+            done: false,
+            timed_out: false,
+            corrupt: false,
+            status: None,
+            progress: Arc::new(Progress::default()),
+        }
+    }
+
+    /// Number of contiguous bytes downloaded so far.
+    pub fn downloaded_len(&self) -> u64 {
+        if self.cache_file.is_some() {
+            self.cached_len
+        } else {
+            self.data.len() as u64
+        }
+    }
+
+    pub fn append(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &self.cache_file {
+            Some(file) => {
+                let mut written = 0;
+                while written < bytes.len() {
+                    written +=
+                        file.seek_write(&bytes[written..], self.cached_len + written as u64)?;
+                }
+                self.cached_len += bytes.len() as u64;
+            }
+            None => self.data.extend_from_slice(bytes),
         }
+        Ok(())
     }
+
+    /// The whole contiguous prefix, read back from the cache file if needed.
+    pub fn contents(&self) -> io::Result<Vec<u8>> {
+        match &self.cache_file {
+            Some(file) => {
+                let mut buffer = vec![0; self.cached_len as usize];
+                read_exact_at(file, &mut buffer, 0)?;
+                Ok(buffer)
+            }
+            None => Ok(self.data.clone()),
+        }
+    }
+
+    fn window_end(&self, offset: u64, len: usize) -> u64 {
+        let end = offset + len as u64;
+        if self.content_length > 0 {
+            end.min(self.content_length)
+        } else {
+            end
+        }
+    }
+
+    fn range_containing(&self, offset: u64, end: u64) -> Option<(u64, &Vec<u8>)> {
+        self.ranges
+            .range(..=offset)
+            .next_back()
+            .filter(|(start, chunk)| **start + chunk.len() as u64 >= end)
+            .map(|(start, chunk)| (*start, chunk))
+    }
+
+    pub fn is_cached(&self, offset: u64, len: usize) -> bool {
+        let end = self.window_end(offset, len);
+        offset >= end
+            || end <= self.downloaded_len()
+            || self.range_containing(offset, end).is_some()
+    }
+
+    /// Copy `[offset, offset + buffer.len())` into `buffer` if it is fully cached.
+    pub fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Option<usize> {
+        let end = self.window_end(offset, buffer.len());
+        if offset >= end {
+            return Some(0);
+        }
+        let len = (end - offset) as usize;
+        if end <= self.downloaded_len() {
+            match &self.cache_file {
+                Some(file) => read_exact_at(file, &mut buffer[..len], offset).ok()?,
+                None => buffer[..len].copy_from_slice(&self.data[offset as usize..end as usize]),
+            }
+            return Some(len);
+        }
+        let (start, chunk) = self.range_containing(offset, end)?;
+        let from = (offset - start) as usize;
+        buffer[..len].copy_from_slice(&chunk[from..from + len]);
+        Some(len)
+    }
+
+    /// Free the downloaded bytes of a stream nothing will read again.
+    pub fn release(&mut self) {
+        self.data = Vec::new();
+        self.ranges.clear();
+    }
+
+    /// Copy a body kept in a cache file into `data` before it is changed. The cache file holds what
+    /// the server sent for every open of the url, and may only be open for reading.
+    fn detach_cache(&mut self) -> io::Result<()> {
+        if self.cache_file.is_some() {
+            self.data = self.contents()?;
+            self.cache_file = None;
+            self.cached_len = 0;
+        }
+        Ok(())
+    }
+
+    /// Keep a window fetched with a Range request, joined with the ranges it touches as long as
+    /// the result fits in `MAX_RANGES_LEN`.
+    pub fn insert_range(&mut self, offset: u64, chunk: Vec<u8>) {
+        let (mut start, mut bytes) = (offset, chunk);
+        let before = self
+            .ranges
+            .range(..=start)
+            .next_back()
+            .map(|(s, c)| (*s, *s + c.len() as u64));
+        if let Some((prev_start, prev_end)) = before {
+            let end = start + bytes.len() as u64;
+            if prev_end >= start && end.max(prev_end) - prev_start <= MAX_RANGES_LEN {
+                let mut prev = self.ranges.remove(&prev_start).unwrap();
+                let tail = prev.split_off((end.min(prev_end) - prev_start) as usize);
+                prev.truncate((start - prev_start) as usize);
+                prev.extend_from_slice(&bytes);
+                prev.extend_from_slice(&tail);
+                (start, bytes) = (prev_start, prev);
+            }
+        }
+        loop {
+            let end = start + bytes.len() as u64;
+            let Some((next_start, next_end)) = self
+                .ranges
+                .range(start + 1..=end)
+                .next()
+                .map(|(s, c)| (*s, *s + c.len() as u64))
+            else {
+                break;
+            };
+            if end.max(next_end) - start > MAX_RANGES_LEN {
+                break;
+            }
+            let next = self.ranges.remove(&next_start).unwrap();
+            if next_end > end {
+                bytes.extend_from_slice(&next[(end - next_start) as usize..]);
+            }
+        }
+        self.ranges.insert(start, bytes);
+        let mut total = self.ranges.values().map(|c| c.len() as u64).sum::<u64>();
+        while total > MAX_RANGES_LEN {
+            let Some(farthest) = self
+                .ranges
+                .keys()
+                .filter(|k| **k != start)
+                .max_by_key(|k| k.abs_diff(start))
+                .copied()
+            else {
+                break;
+            };
+            total -= self.ranges.remove(&farthest).unwrap().len() as u64;
+        }
+    }
+
+    /// Overwrite the contiguous prefix at `offset`, growing it with zeros as needed.
+    pub fn write_at(&mut self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        self.detach_cache()?;
+        let end = offset + bytes.len() as u64;
+        if self.data.len() < end as usize {
+            self.data.resize(end as usize, 0);
+        }
+        self.data[offset as usize..end as usize].copy_from_slice(bytes);
+        self.content_length = self.downloaded_len();
+        self.modified = true;
+        Ok(())
+    }
+
+    pub fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.detach_cache()?;
+        self.data.resize(len as usize, 0);
+        self.ranges.clear();
+        self.content_length = len;
+        self.modified = true;
+        Ok(())
+    }
+}
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.seek_read(&mut buffer[read..], offset + read as u64)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+    Ok(())
 }
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, serde::Serialize)]
 pub struct Attributes {
     pub value: u32,
@@ -92,6 +326,14 @@ impl Stat {
         }
     }
 
+    /// Stamp every timestamp with `time`, for entries that have no time of their own.
+    pub fn with_times(mut self, time: SystemTime) -> Self {
+        self.ctime = time;
+        self.mtime = time;
+        self.atime = time;
+        self
+    }
+
     pub fn update_atime(&mut self, atime: SystemTime) {
         self.atime = atime;
     }
@@ -101,3 +343,64 @@ impl Stat {
         self.mtime = mtime;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alt_stream_read_at_sparse() {
+        let mut stream = AltStream::new();
+        stream.content_length = 10;
+        stream.data.extend_from_slice(b"012");
+        stream.insert_range(6, b"6789".to_vec());
+
+        let mut buf = [0u8; 4];
+        assert_eq!(stream.read_at(0, &mut buf[..3]), Some(3));
+        assert_eq!(&buf[..3], b"012");
+        assert_eq!(stream.read_at(2, &mut buf), None);
+        assert!(!stream.is_cached(2, 4));
+        assert_eq!(stream.read_at(7, &mut buf), Some(3));
+        assert_eq!(&buf[..3], b"789");
+        assert_eq!(stream.read_at(12, &mut buf), Some(0));
+    }
+
+    #[test]
+    fn test_alt_stream_insert_range_merges() {
+        let mut stream = AltStream::new();
+        stream.insert_range(4, b"45".to_vec());
+        stream.insert_range(8, b"89".to_vec());
+        // Fills the gap and overlaps both neighbours.
+        stream.insert_range(5, b"5678".to_vec());
+        assert_eq!(stream.ranges.len(), 1);
+        assert_eq!(stream.ranges[&4], b"456789");
+        stream.insert_range(2, b"23".to_vec());
+        assert_eq!(stream.ranges[&2], b"23456789");
+        stream.insert_range(3, b"3".to_vec());
+        assert_eq!(stream.ranges[&2], b"23456789");
+
+        // Far apart windows past the cap push out the farthest ones.
+        let window = (MAX_RANGES_LEN / 2) as usize;
+        let mut stream = AltStream::new();
+        for i in 0..3u64 {
+            stream.insert_range(i * 2 * window as u64, vec![0; window]);
+        }
+        assert_eq!(
+            stream.ranges.keys().copied().collect::<Vec<_>>(),
+            [2 * window as u64, 4 * window as u64]
+        );
+    }
+
+    #[test]
+    fn test_alt_stream_write_at() {
+        let mut stream = AltStream::new();
+        stream.append(b"hello").unwrap();
+        stream.write_at(1, b"EL").unwrap();
+        stream.write_at(7, b"!").unwrap();
+        assert_eq!(stream.contents().unwrap(), b"hELlo\0\0!");
+        assert_eq!(stream.content_length, 8);
+        stream.truncate(2).unwrap();
+        assert_eq!(stream.contents().unwrap(), b"hE");
+        assert!(stream.modified);
+    }
+}
@@ -1,23 +1,41 @@
+mod crawl;
 mod fs;
 mod path;
+mod reexport;
 mod security;
 mod thread_pool;
 mod utils;
+mod verify;
 mod windows;
 
-use std::{fs::File, io::BufReader, sync::Arc};
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
 
 use clap::{builder::FalseyValueParser, Arg, ArgMatches, Command};
-use dokan::{init, shutdown, unmount, FileSystemMounter, MountFlags, MountOptions};
+use dokan::{
+    init, shutdown, unmount, FileSystemMountError, FileSystemMounter, MountFlags, MountOptions,
+};
 
 use fs::{
-    entry::{DirEntry, Entry, EntryName},
-    handler::MemFsHandler,
+    entry::{DirEntry, Entry, EntryName, EntryNameRef, HttpFileEntry},
+    handler::{MemFsHandler, MemFsOptions},
     metadata::Stat,
 };
-use ignore::gitignore::{Gitignore, GitignoreBuilder};
-use log::debug;
-use security::SecurityDescriptor;
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::OverrideBuilder,
+};
+use log::{debug, warn};
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Client,
+};
+use serde_json::error::Category;
 use thread_pool::ThreadPool;
 use url::Url;
 use widestring::{U16CString, U16String};
@@ -44,7 +62,7 @@ OPTIONS:
                 .num_args(1)
                 .value_name("MOUNT_POINT")
                 .required(true)
-                .help("Mount point path."),
+                .help("Mount point path, a drive letter like Z: or an existing empty NTFS directory, which leaves the drive letters free."),
         )
         .arg(
             Arg::new("url")
@@ -53,7 +71,7 @@ OPTIONS:
                 .num_args(1)
                 .value_name("URL")
                 .required(true)
-                .help("http url."),
+                .help("http url of the mounted directory, a trailing slash is added if missing."),
         )
         .arg(
             Arg::new("dir")
@@ -61,8 +79,9 @@ OPTIONS:
                 .long("dir_tree")
                 .num_args(1)
                 .value_name("DIR_TREE")
-                .required(true)
-                .help("dir tree in json format."),
+                .required_unless_present("autoindex")
+                .conflicts_with("autoindex")
+                .help("dir tree in json, yaml or toml format by extension, a local file or an http(s) url."),
         )
         .arg(
             Arg::new("fs_ignore")
@@ -105,7 +124,633 @@ OPTIONS:
                 .value_parser(FalseyValueParser::new())
                 .help("Mount as a removable drive."),
         )
+        .arg(
+            Arg::new("reexport_port")
+                .long("reexport-port")
+                .num_args(1)
+                .value_name("PORT")
+                .value_parser(clap::value_parser!(u16))
+                .help("Also serve the mounted content read-only at http://127.0.0.1:PORT/."),
+        )
+        .arg(
+            Arg::new("wait_for_mount_point")
+                .long("wait-for-mount-point")
+                .num_args(1)
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Keep retrying for up to SECONDS while the mount point is busy."),
+        )
+        .arg(
+            Arg::new("cache_dir")
+                .long("cache-dir")
+                .num_args(1)
+                .value_name("DIR")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Store downloaded files in DIR instead of memory. Removed on unmount unless --cache-persist."),
+        )
+        .arg(
+            Arg::new("cache_key")
+                .long("cache-key")
+                .num_args(1)
+                .value_name("STRATEGY")
+                .value_parser(["url", "url-no-query", "etag"])
+                .default_value("url")
+                .help("What identifies a file in --cache-dir. `etag` sends a HEAD request first."),
+        )
+        .arg(
+            Arg::new("inject_latency_ms")
+                .long("inject-latency-ms")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Testing: delay every download and read by MS milliseconds."),
+        )
+        .arg(
+            Arg::new("inject_failure_rate")
+                .long("inject-failure-rate")
+                .num_args(1)
+                .value_name("RATE")
+                .value_parser(parse_rate)
+                .default_value("0")
+                .help("Testing: fail this fraction (0.0-1.0) of downloads and reads."),
+        )
+        .arg(
+            Arg::new("inject_seed")
+                .long("inject-seed")
+                .num_args(1)
+                .value_name("SEED")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Testing: seed for --inject-failure-rate, the same seed fails the same operations."),
+        )
+        .arg(
+            Arg::new("header")
+                .short('H')
+                .long("header")
+                .num_args(1)
+                .value_name("NAME: VALUE")
+                .action(clap::ArgAction::Append)
+                .value_parser(utils::parse_header)
+                .help("Send this header with every request, e.g. an Authorization token. Repeatable."),
+        )
+        .arg(
+            Arg::new("user_agent")
+                .long("user-agent")
+                .num_args(1)
+                .value_name("AGENT")
+                .default_value(concat!("http_filesystem_bridge/", env!("CARGO_PKG_VERSION")))
+                .value_parser(parse_user_agent)
+                .help("User-Agent sent to the backend, an empty string sends none."),
+        )
+        .arg(
+            Arg::new("proxy")
+                .long("proxy")
+                .num_args(1)
+                .value_name("URL")
+                .value_parser(parse_proxy)
+                .conflicts_with("no_proxy")
+                .help("Send requests through this proxy, e.g. http://proxy:3128 or socks5://127.0.0.1:1080."),
+        )
+        .arg(
+            Arg::new("no_proxy")
+                .long("no-proxy")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Ignore proxies set in the environment (HTTP_PROXY, HTTPS_PROXY, ALL_PROXY)."),
+        )
+        .arg(
+            Arg::new("deduplicate_streams")
+                .long("deduplicate-streams")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Opens of a file that is already open share one download."),
+        )
+        .arg(
+            Arg::new("read_ahead_bytes")
+                .long("read-ahead-bytes")
+                .num_args(1)
+                .value_name("BYTES")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1048576")
+                .help("Bytes to prefetch past a sequential read that is ahead of the download, 0 disables it."),
+        )
+        .arg(
+            Arg::new("autoindex")
+                .long("autoindex")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Build the dir tree from the server's directory listing pages instead of --dir_tree."),
+        )
+        .arg(
+            Arg::new("max_depth")
+                .long("max-depth")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("8")
+                .help("With --autoindex, list directories at most N levels below the url."),
+        )
+        .arg(
+            Arg::new("listing_concurrency")
+                .long("listing-concurrency")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("4")
+                .help("With --autoindex, fetch at most N directory listings at once."),
+        )
+        .arg(
+            Arg::new("flatten")
+                .long("flatten")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Show every file of the dir tree directly under the mount point, numbering names that collide."),
+        )
+        .arg(
+            Arg::new("preopen")
+                .long("preopen")
+                .num_args(1)
+                .value_name("GLOB")
+                .action(clap::ArgAction::Append)
+                .help("Download the dir tree files matching GLOB at mount time and keep them in memory. Repeatable."),
+        )
+        .arg(
+            Arg::new("verify_structure")
+                .long("verify-structure")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Before mounting, compare the dir tree with the server's directory listings and refuse to mount if they differ."),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Send a HEAD for every file of the dir tree, report the ones that fail and exit without mounting."),
+        )
+        .arg(
+            Arg::new("manifest_only")
+                .long("manifest-only")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Only serve files declared in the dir tree, opening anything else fails as not found."),
+        )
+        .arg(
+            Arg::new("pool_size")
+                .long("pool-size")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("20")
+                .help("Number of download worker threads. Unrelated to --single-thread, which only affects Dokan's dispatch threads."),
+        )
+        .arg(
+            Arg::new("queue_capacity")
+                .long("queue-capacity")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1024")
+                .help("Downloads that can wait for a worker thread, opening more files waits until one starts."),
+        )
+        .arg(
+            Arg::new("response_header_timeout_ms")
+                .long("response-header-timeout-ms")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Fail a request when the server sends no response headers within MS milliseconds, 0 waits forever."),
+        )
+        .arg(
+            Arg::new("append_ext_from_type")
+                .long("append-ext-from-type")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("List extensionless files with the extension of their content type, e.g. `photo` served as image/jpeg is listed as `photo.jpg`."),
+        )
+        .arg(
+            Arg::new("sniff_content_type")
+                .long("sniff-content-type")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Detect the content type from the first bytes of each download instead of trusting the server."),
+        )
+        .arg(
+            Arg::new("retries")
+                .long("retries")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("3")
+                .help("Retry a download N times after connection failures and 5xx responses."),
+        )
+        .arg(
+            Arg::new("retry_base_ms")
+                .long("retry-base-ms")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("250")
+                .help("Delay before the first retry, doubled for each one after it."),
+        )
+        .arg(
+            Arg::new("max_redirects")
+                .long("max-redirects")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .help("Follow at most N redirects per request, 0 treats any redirect as an error."),
+        )
+        .arg(
+            Arg::new("warn_large_dir")
+                .long("warn-large-dir")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .help("Log a warning when a directory with more than N entries is listed, 0 disables it."),
+        )
+        .arg(
+            Arg::new("truncate_large_dir")
+                .long("truncate-large-dir")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .requires("warn_large_dir")
+                .help("With --warn-large-dir, list only the first N entries followed by a marker entry."),
+        )
+        .arg(
+            Arg::new("writable")
+                .long("writable")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Allow writing to http files, their new content is sent back with a PUT when the file is flushed or closed."),
+        )
+        .arg(
+            Arg::new("stable_file_index")
+                .long("stable-file-index")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Derive file indexes from paths so they stay the same across mounts."),
+        )
+        .arg(
+            Arg::new("max_name_length")
+                .long("max-name-length")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("255")
+                .help("Reject file and stream names longer than N characters."),
+        )
+        .arg(
+            Arg::new("read_timeout_ms")
+                .long("read-timeout-ms")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("5000")
+                .help("Fail a read that waits MS milliseconds without the download making progress, 0 waits forever."),
+        )
+        .arg(
+            Arg::new("poll_interval_ms")
+                .long("poll-interval-ms")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("10")
+                .help("How often a waiting read checks the download."),
+        )
+        .arg(
+            Arg::new("probe_retries")
+                .long("probe-retries")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("2")
+                .help("Retry a request that only fetches a file's size N times, separately from --retries."),
+        )
+        .arg(
+            Arg::new("serve_error_body")
+                .long("serve-error-body")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Debugging: serve the body of 4xx and 5xx responses as the file content instead of failing reads. Files with an error body get a `:status` stream holding the HTTP status."),
+        )
+        .arg(
+            Arg::new("max_bps")
+                .long("max-bps")
+                .num_args(1)
+                .value_name("BYTES_PER_SEC")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Limit the combined speed of all downloads to BYTES_PER_SEC, 0 means unlimited."),
+        )
+        .arg(
+            Arg::new("synthetic_time")
+                .long("synthetic-time")
+                .num_args(1)
+                .value_name("UNIX_SECS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Timestamp of the root and of the directories without an mtime of their own, defaults to the mount time."),
+        )
+        .arg(
+            Arg::new("prefetch")
+                .long("prefetch")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .help("When a file is opened, download up to N other files of its directory in the background, 0 disables it."),
+        )
+        .arg(
+            Arg::new("no_cache")
+                .long("no-cache")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Always download again instead of serving files already in --cache-dir."),
+        )
+        .arg(
+            Arg::new("allow_host")
+                .long("allow-host")
+                .num_args(1)
+                .value_name("HOST")
+                .action(clap::ArgAction::Append)
+                .help("Only accept dir tree urls pointing to the --url host or HOST. Repeatable."),
+        )
+        .arg(
+            Arg::new("allow_invalid_urls")
+                .long("allow-invalid-urls")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Mount even if some dir tree urls are invalid, those files are fetched from their path."),
+        )
+        .arg(
+            Arg::new("content_length_from")
+                .long("content-length-from")
+                .num_args(1)
+                .value_name("SOURCES")
+                .default_value("header,head,manifest,download")
+                .help("Comma separated sources of a file's size, the first one that knows it wins: `header` of the download, a separate `head` request, the dir tree's `manifest` size or the `download`ed length."),
+        )
+        .arg(
+            Arg::new("download_chunks")
+                .long("download-chunks")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .help("Download files of a server that accepts ranges over up to N parallel connections, in parts of at least 1 MiB."),
+        )
+        .arg(
+            Arg::new("auto_create_dirs")
+                .long("auto-create-dirs")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("manifest_only")
+                .help("Create the directories of opened paths that aren't in the dir tree instead of failing with path not found."),
+        )
+        .arg(
+            Arg::new("url_rewrite")
+                .long("url-rewrite")
+                .num_args(1)
+                .value_name("FROM=>TO")
+                .value_parser(parse_url_rewrite)
+                .action(clap::ArgAction::Append)
+                .help("Replace FROM with TO in the url of every request, in the given order. Repeatable."),
+        )
+        .arg(
+            Arg::new("volume_size")
+                .long("volume-size")
+                .num_args(1)
+                .value_name("SIZE")
+                .value_parser(utils::parse_size)
+                .default_value("1G")
+                .help("Total size of the volume reported to applications, like 10G."),
+        )
+        .arg(
+            Arg::new("free_space")
+                .long("free-space")
+                .num_args(1)
+                .value_name("SIZE")
+                .value_parser(utils::parse_size)
+                .default_value("512M")
+                .help("Free space reported to applications, at most --volume-size."),
+        )
+        .arg(
+            Arg::new("volume_label")
+                .long("volume-label")
+                .num_args(1)
+                .value_name("LABEL")
+                .value_parser(parse_volume_label)
+                .default_value("Http FileSystem")
+                .help("Name of the volume shown in Explorer."),
+        )
+        .arg(
+            Arg::new("serial")
+                .long("serial")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("0")
+                .help("Serial number of the volume."),
+        )
+        .arg(
+            Arg::new("connect_timeout_ms")
+                .long("connect-timeout-ms")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Fail a request when the connection to the server takes more than MS milliseconds, 0 waits forever."),
+        )
+        .arg(
+            Arg::new("request_timeout_ms")
+                .long("request-timeout-ms")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Fail a request, body included, that takes more than MS milliseconds, 0 waits forever."),
+        )
+        .arg(
+            Arg::new("stats_file")
+                .long("stats-file")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Serve download counters as JSON in a read-only .stats.json at the root, and the downloads in progress in .downloads.json."),
+        )
+        .arg(
+            Arg::new("ignore_file")
+                .long("ignore-file")
+                .num_args(1)
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(clap::ArgAction::Append)
+                .help("Read ignore rules from PATH instead of the default files, implies --fs-ignore. Repeatable."),
+        )
+        .arg(
+            Arg::new("ignore_root")
+                .long("ignore-root")
+                .num_args(1)
+                .value_name("DIR")
+                .value_parser(clap::value_parser!(PathBuf))
+                .default_value(".")
+                .help("Directory holding the default ignore files, rules are relative to it."),
+        )
+        .arg(
+            Arg::new("network_drive")
+                .long("network-drive")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("removable")
+                .help("Mount as a network drive that Explorer can disconnect. Can't be combined with --removable."),
+        )
+        .arg(
+            Arg::new("unc_name")
+                .long("unc-name")
+                .num_args(1)
+                .value_name("UNC")
+                .requires("network_drive")
+                .help("UNC path the network drive is also reachable at, like \\\\http\\site."),
+        )
+        .arg(
+            Arg::new("read_only")
+                .long("read-only")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("writable")
+                .help("Mount the volume write-protected, so applications know not to try writing."),
+        )
+        .arg(
+            Arg::new("cache_persist")
+                .long("cache-persist")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .requires("cache_dir")
+                .help("Keep complete files in --cache-dir after unmounting and reuse them on the next mount once the server confirms they are current."),
+        )
+        .arg(
+            Arg::new("index_file")
+                .long("index-file")
+                .num_args(1)
+                .value_name("NAME")
+                .value_parser(parse_index_file)
+                .default_value("index.html")
+                .help("File requested when a directory url is opened, like index.htm or default.aspx."),
+        )
+        .arg(
+            Arg::new("max_path")
+                .long("max-path")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("260")
+                .help("Reject paths inside the volume longer than N characters, raise it for apps using extended-length paths."),
+        )
+        .arg(
+            Arg::new("no_mount_info")
+                .long("no-mount-info")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Don't add the read-only .mount-info.txt describing the mount at the root."),
+        )
+        .arg(
+            Arg::new("owner")
+                .long("owner")
+                .num_args(1)
+                .value_name("SID")
+                .value_parser(security::parse_sid)
+                .help("Owner of every entry, like S-1-5-21-1004336348-1177238915-682003330-1001. Defaults to the local system."),
+        )
+        .arg(
+            Arg::new("everyone_read_only")
+                .long("everyone-read-only")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue)
+                .help("Let everyone read and execute entries, only administrators and the system can change them."),
+        )
+        .arg(
+            Arg::new("base_path")
+                .long("base-path")
+                .num_args(1)
+                .value_name("PATH")
+                .help("Directory below --url the dir tree paths start from, like /files/."),
+        )
+        .arg(
+            Arg::new("strip_prefix")
+                .long("strip-prefix")
+                .num_args(1)
+                .value_name("PATH")
+                .help("Leading directory removed from the paths of the mount before they are joined to the url."),
+        )
+        .arg(
+            Arg::new("timeout_on_open")
+                .long("timeout-on-open")
+                .num_args(1)
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Wait up to MS milliseconds for the server to answer when a file is opened, so a missing file or an unreachable server fails the open. 0 opens without waiting."),
+        )
+        .arg(
+            Arg::new("connect_limit")
+                .long("connect-limit")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .help("Keep at most N requests to the server open at once whatever the pool size, including chunks, range reads and HEADs. The others wait their turn. 0 means unlimited."),
+        )
+}
+
+fn parse_proxy(s: &str) -> Result<Url, String> {
+    let url = Url::parse(s).map_err(|e| format!("`{s}` is not a proxy URL: {e}"))?;
+    reqwest::Proxy::all(url.clone()).map_err(|e| format!("unsupported proxy `{s}`: {e}"))?;
+    Ok(url)
+}
+
+fn parse_user_agent(s: &str) -> Result<String, String> {
+    HeaderValue::from_str(s).map_err(|e| format!("invalid User-Agent `{s}`: {e}"))?;
+    Ok(s.to_string())
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("`{s}` is not a number between 0.0 and 1.0")),
+    }
+}
+
+fn parse_volume_label(s: &str) -> Result<String, String> {
+    match s.encode_utf16().count() {
+        0 => Err("the volume label can't be empty".to_string()),
+        _ if s.contains('\0') => Err("the volume label can't contain NUL".to_string()),
+        len if len > path::MAX_COMPONENT_LENGTH as usize => Err(format!(
+            "the volume label is {len} characters long, the limit is {}",
+            path::MAX_COMPONENT_LENGTH
+        )),
+        _ => Ok(s.to_string()),
+    }
+}
+
+fn parse_index_file(s: &str) -> Result<String, String> {
+    match s {
+        "" => Err("the index file name can't be empty".to_string()),
+        _ if s.contains(['/', '\\']) => Err(format!("`{s}` is a path, not a file name")),
+        _ => Ok(s.to_string()),
+    }
+}
+
+fn parse_url_rewrite(s: &str) -> Result<(String, String), String> {
+    match s.split_once("=>") {
+        Some((from, to)) if !from.is_empty() => Ok((from.to_string(), to.to_string())),
+        _ => Err(format!("`{s}` is not a FROM=>TO rewrite")),
+    }
 }
+
 fn arg_parser() -> ArgMatches {
     command().get_matches()
 }
@@ -161,34 +806,589 @@ mod tests {
         assert!(!matches.get_flag("single_thread"));
         assert!(!matches.get_flag("dokan_debug"));
         assert!(!matches.get_flag("removable"));
+        assert_eq!(matches.get_one::<u64>("pool_size"), Some(&20));
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let mut children = HashMap::new();
+        let stat = || {
+            Stat::new(
+                0,
+                0,
+                security::SecurityDescriptor::new_default().unwrap(),
+                Default::default(),
+            )
+        };
+        for name in ["a.txt", "A (2).txt", "README"] {
+            children.insert(
+                EntryName(U16String::from_str(name)),
+                Arc::new(Entry::Directory(Arc::new(DirEntry::new(stat())))),
+            );
+        }
+        let name = |n| unique_name(&children, n).0.to_string_lossy();
+        assert_eq!(name("b.txt"), "b.txt");
+        assert_eq!(name("a.txt"), "a (3).txt");
+        assert_eq!(name("readme"), "readme (2)");
     }
-}
 
-fn opt_ignore(enable: bool) -> Option<Gitignore> {
-    match enable {
-        true => {
-            let mut gitignore_builder = GitignoreBuilder::new(".");
-            let ignores = [
-                gitignore_builder.add(".gitignore"),
-                gitignore_builder.add(".ignore"),
-                gitignore_builder.add(".fsignore"),
+    #[test]
+    fn test_build_tree_with_files() {
+        let handler = MemFsHandler::new(
+            Url::parse("http://example.com/site/").unwrap(),
+            Arc::new(ThreadPool::new(1)),
+            None,
+            MemFsOptions::default(),
+        );
+        let dir_tree = serde_json::from_str(
+            r#"{"name": "/", "children": [
+                {"name": "index.html", "size": 12, "mtime": 1735689600},
+                {"name": "js/", "children": [
+                    {"name": "app.js", "url": "https://cdn.example.com/app.js"}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(build_tree(&handler, dir_tree, false, &[]).is_empty());
+
+        let child = |dir: &DirEntry, name: &str| {
+            let children = dir.children.read().unwrap();
+            children
+                .get(EntryNameRef::new(&U16String::from_str(name)))
+                .unwrap()
+                .as_ref()
+                .clone()
+        };
+        let Entry::HttpFile(index) = child(&handler.root, "index.html") else {
+            panic!("index.html is not a file");
+        };
+        assert_eq!(index.size(), 12);
+        // The declared time is kept over the `Last-Modified` of downloads.
+        assert_eq!(
+            index.stat.read().unwrap().mtime,
+            UNIX_EPOCH + Duration::from_secs(1735689600)
+        );
+        assert!(*index.times_pinned.read().unwrap());
+        assert_eq!(
+            index.url.read().unwrap().as_ref().unwrap().as_str(),
+            "http://example.com/site/index.html"
+        );
+        let Entry::Directory(js) = child(&handler.root, "js") else {
+            panic!("js is not a directory");
+        };
+        let Entry::HttpFile(app) = child(&js, "app.js") else {
+            panic!("app.js is not a file");
+        };
+        assert_eq!(app.size(), 0);
+        assert!(!*app.times_pinned.read().unwrap());
+        assert_eq!(
+            app.url.read().unwrap().as_ref().unwrap().as_str(),
+            "https://cdn.example.com/app.js"
+        );
+    }
+
+    #[test]
+    fn test_build_tree_encodes_paths() {
+        let handler = MemFsHandler::new(
+            Url::parse("http://example.com/site/").unwrap(),
+            Arc::new(ThreadPool::new(1)),
+            None,
+            MemFsOptions {
+                strip_prefix: "pub".to_string(),
+                ..Default::default()
+            },
+        );
+        let dir_tree = serde_json::from_str(
+            r##"{"name": "/", "children": [
+                {"name": "pub/", "children": [
+                    {"name": "q?.txt"},
+                    {"name": "#1 a.txt"}
+                ]}
+            ]}"##,
+        )
+        .unwrap();
+        assert!(build_tree(&handler, dir_tree, false, &[]).is_empty());
+        let Entry::Directory(dir) = handler
+            .root
+            .children
+            .read()
+            .unwrap()
+            .get(EntryNameRef::new(&U16String::from_str("pub")))
+            .unwrap()
+            .as_ref()
+            .clone()
+        else {
+            panic!("pub is not a directory");
+        };
+        let url = |name: &str| {
+            let children = dir.children.read().unwrap();
+            let Entry::HttpFile(file) = children
+                .get(EntryNameRef::new(&U16String::from_str(name)))
+                .unwrap()
+                .as_ref()
+            else {
+                panic!("{name} is not a file");
+            };
+            let url = file.url.read().unwrap().as_ref().unwrap().to_string();
+            url
+        };
+        assert_eq!(url("q?.txt"), "http://example.com/site/q%3F.txt");
+        assert_eq!(url("#1 a.txt"), "http://example.com/site/%231%20a.txt");
+    }
+
+    #[test]
+    fn test_build_large_tree() {
+        let handler = MemFsHandler::new(
+            Url::parse("http://example.com/").unwrap(),
+            Arc::new(ThreadPool::new(1)),
+            None,
+            MemFsOptions::default(),
+        );
+        let dir_tree = utils::DirTree {
+            name: "/".to_string(),
+            children: (0..100)
+                .map(|d| utils::DirTree {
+                    name: format!("dir{d}/"),
+                    children: (0..200)
+                        .map(|f| utils::DirTree {
+                            name: format!("file{f}.bin"),
+                            size: Some(f),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        assert!(build_tree(&handler, dir_tree, false, &[]).is_empty());
+
+        let mut ids = std::collections::HashSet::new();
+        let root = handler.root.children.read().unwrap();
+        assert_eq!(root.len(), 100);
+        for dir in root.values() {
+            let Entry::Directory(dir) = dir.as_ref() else {
+                panic!("not a directory");
+            };
+            assert!(ids.insert(dir.stat.read().unwrap().id));
+            let children = dir.children.read().unwrap();
+            assert_eq!(children.len(), 200);
+            for file in children.values() {
+                let stat = file.stat().read().unwrap();
+                assert!(ids.insert(stat.id));
+                assert!(Arc::ptr_eq(&stat.parent.upgrade().unwrap(), dir));
+            }
+        }
+        let Entry::Directory(dir) = root
+            .get(EntryNameRef::new(&U16String::from_str("dir42")))
+            .unwrap()
+            .as_ref()
+        else {
+            panic!("not a directory");
+        };
+        let Entry::HttpFile(file) = dir
+            .children
+            .read()
+            .unwrap()
+            .get(EntryNameRef::new(&U16String::from_str("file7.bin")))
+            .unwrap()
+            .as_ref()
+            .clone()
+        else {
+            panic!("not a file");
+        };
+        assert_eq!(file.size(), 7);
+    }
+
+    #[test]
+    fn test_check_mount_point() {
+        assert_eq!(check_mount_point("Z:"), Ok("Z:".to_string()));
+        assert_eq!(check_mount_point("z:\\"), Ok("z:\\".to_string()));
+
+        let dir = std::env::temp_dir().join(format!("http_fs_mount_{}", std::process::id()));
+        let missing = check_mount_point(dir.to_str().unwrap()).unwrap_err();
+        assert!(missing.ends_with("doesn't exist, create an empty directory there first"));
+        std::fs::create_dir(&dir).unwrap();
+        assert_eq!(
+            check_mount_point(dir.to_str().unwrap()),
+            Ok(dir.to_string_lossy().into_owned())
+        );
+        let file = dir.join("a.txt");
+        std::fs::write(&file, b"a").unwrap();
+        let not_empty = check_mount_point(dir.to_str().unwrap()).unwrap_err();
+        assert!(not_empty.ends_with("is not empty"));
+        let not_dir = check_mount_point(file.to_str().unwrap()).unwrap_err();
+        assert!(not_dir.ends_with("is not a directory"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_mount_error_message() {
+        assert!(mount_error_message(&FileSystemMountError::DriverInstall).contains("not installed"));
+        assert!(mount_error_message(&FileSystemMountError::Mount).contains("already in use"));
+        assert!(mount_error_message(&FileSystemMountError::DriveLetter)
+            .starts_with("invalid drive letter"));
+    }
+
+    #[test]
+    fn test_mount_point_busy() {
+        assert!(mount_point_busy(&FileSystemMountError::Mount));
+        assert!(!mount_point_busy(&FileSystemMountError::MountPoint));
+        assert!(!mount_point_busy(&FileSystemMountError::DriveLetter));
+        assert!(!mount_point_busy(&FileSystemMountError::General));
+    }
+
+    #[test]
+    fn test_parse_dir_tree() {
+        let parse =
+            |json: &str| parse_dir_tree("tree.json", Some("json"), json.as_bytes()).map(|_| ());
+        assert_eq!(parse(r#"{"name": "/", "children": []}"#), Ok(()));
+        assert_eq!(
+            parse("{\"name\": \"/\",\n \"children\": [}"),
+            Err("tree.json:2:15: malformed JSON: expected value at line 2 column 15".to_string())
+        );
+        assert_eq!(
+            parse("{\"name\": \"/\",\n \"children\": [{\"name\": \"a\", \"size\": \"12\"}]}"),
+            Err("tree.json:2:40: invalid dir tree: invalid type: string \"12\", expected u64 at line 2 column 40".to_string())
+        );
+        assert_eq!(
+            parse("{\"name\": \"/\""),
+            Err(
+                "tree.json:1:12: truncated JSON: EOF while parsing an object at line 1 column 12"
+                    .to_string()
+            )
+        );
+
+        let yaml = "name: /\nchildren:\n  - name: a.txt\n    size: 12\n  - name: docs/\n    children:\n      - name: b.txt\n";
+        let dir_tree = parse_dir_tree("tree.yml", Some("yml"), yaml.as_bytes()).unwrap();
+        assert_eq!(dir_tree.children[0].size, Some(12));
+        assert_eq!(dir_tree.children[1].children[0].name, "b.txt");
+        let yaml = "name: /\nchildren:\n  - name: a.txt\n    size: twelve\n";
+        let e = parse_dir_tree("tree.yml", Some("YML"), yaml.as_bytes()).unwrap_err();
+        assert!(e.starts_with("tree.yml:"), "{e}");
+        assert!(e.contains("invalid YAML"), "{e}");
+
+        let toml = "name = \"/\"\n[[children]]\nname = \"a.txt\"\nsize = 12\n[[children]]\nname = \"docs/\"\n[[children.children]]\nname = \"b.txt\"\n";
+        let dir_tree = parse_dir_tree("tree.toml", Some("toml"), toml.as_bytes()).unwrap();
+        assert_eq!(dir_tree.children[0].size, Some(12));
+        assert_eq!(dir_tree.children[1].children[0].name, "b.txt");
+        let toml = "name = \"/\"\n\n[[children]]\nname = \"a.txt\"\nsize = \"12\"\n";
+        assert_eq!(
+            parse_dir_tree("tree.toml", Some("toml"), toml.as_bytes()).map(|_| ()),
+            Err(
+                "tree.toml:5:8: invalid TOML: invalid type: string \"12\", expected u64"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_network_drive_is_not_removable() {
+        let matches = |args: &[&str]| {
+            let mut argv = vec![
+                "test_binary",
+                "--mount-point",
+                "C:\\mount",
+                "--url",
+                "http://example.com",
+            ];
+            argv.extend_from_slice(args);
+            command().try_get_matches_from(argv)
+        };
+        assert!(matches(&["--network-drive", "--unc-name", "\\\\http\\site"]).is_ok());
+        assert!(matches(&["--network-drive", "--removable"]).is_err());
+        assert!(matches(&["--unc-name", "\\\\http\\site"]).is_err());
+    }
+
+    #[test]
+    fn test_volume_label() {
+        let matches = |label: &str| {
+            command().try_get_matches_from(vec![
+                "test_binary",
+                "--mount-point",
+                "C:\\mount",
+                "--url",
+                "http://example.com",
+                "--volume-label",
+                label,
+            ])
+        };
+        assert_eq!(
+            matches("Mirror")
+                .unwrap()
+                .get_one::<String>("volume_label")
+                .unwrap(),
+            "Mirror"
+        );
+        assert!(matches("").is_err());
+        assert!(matches(&"x".repeat(256)).is_err());
+    }
+
+    #[test]
+    fn test_owner() {
+        let matches = |args: &[&str]| {
+            let mut argv = vec![
+                "test_binary",
+                "--mount-point",
+                "C:\\mount",
+                "--url",
+                "http://example.com",
             ];
-            if ignores.iter().all(|res| res.is_some()) {
-                panic!("Failed to add ignore files");
+            argv.extend_from_slice(args);
+            command().try_get_matches_from(argv)
+        };
+        let owner = matches(&["--owner", "S-1-5-32-544"]).unwrap();
+        assert_eq!(
+            owner.get_one::<Vec<u8>>("owner").unwrap(),
+            &[1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 0x20, 2, 0, 0]
+        );
+        assert_eq!(
+            security::parse_sid("S-1-1-0").unwrap(),
+            [1, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]
+        );
+        assert!(matches(&["--owner", "S-1-5-21-1004336348-1177238915-682003330-1001"]).is_ok());
+        assert!(matches(&["--owner", "S-2-5-32"]).is_err());
+        assert!(matches(&["--owner", "Administrators"]).is_err());
+        assert!(matches(&["--owner", "S-1-5-x"]).is_err());
+        assert!(matches(&["--owner", &format!("S-1-5{}", "-1".repeat(16))]).is_err());
+    }
+
+    #[test]
+    fn test_index_file() {
+        let matches = |args: &[&str]| {
+            let mut argv = vec![
+                "test_binary",
+                "--mount-point",
+                "C:\\mount",
+                "--url",
+                "http://example.com",
+            ];
+            argv.extend_from_slice(args);
+            command().try_get_matches_from(argv)
+        };
+        let index_file = |args: &[&str]| {
+            matches(args)
+                .unwrap()
+                .get_one::<String>("index_file")
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(index_file(&[]), "index.html");
+        assert_eq!(
+            index_file(&["--index-file", "default.aspx"]),
+            "default.aspx"
+        );
+        assert!(matches(&["--index-file", ""]).is_err());
+        assert!(matches(&["--index-file", "docs/index.htm"]).is_err());
+    }
+
+    #[test]
+    fn test_user_agent() {
+        let matches = |agent: &str| {
+            command().try_get_matches_from(vec![
+                "test_binary",
+                "--mount-point",
+                "C:\\mount",
+                "--url",
+                "http://example.com",
+                "--user-agent",
+                agent,
+            ])
+        };
+        assert_eq!(
+            matches("curl/8.0")
+                .unwrap()
+                .get_one::<String>("user_agent")
+                .unwrap(),
+            "curl/8.0"
+        );
+        assert!(matches("").is_ok());
+        assert!(matches("bad\nagent").is_err());
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let base = Url::parse("http://example.com/site/").unwrap();
+        let allowed = ["cdn.example.com".to_string()];
+        assert_eq!(
+            resolve_url(&base, "js/app.js", &allowed).unwrap().as_str(),
+            "http://example.com/site/js/app.js"
+        );
+        assert!(resolve_url(&base, "https://CDN.example.com/app.js", &allowed).is_ok());
+        assert!(resolve_url(&base, "https://evil.example.net/app.js", &[]).is_ok());
+        assert_eq!(
+            resolve_url(&base, "https://evil.example.net/app.js", &allowed),
+            Err("host `evil.example.net` is not allowed".to_string())
+        );
+        assert_eq!(
+            resolve_url(&base, "ftp://example.com/app.js", &[]),
+            Err("unsupported scheme `ftp`".to_string())
+        );
+        assert!(resolve_url(&base, "http://exa mple.com/", &[]).is_err());
+    }
+
+    #[test]
+    fn test_arg_parser_rejects_empty_pool() {
+        let matches = command().try_get_matches_from(vec![
+            "test_binary",
+            "--mount-point",
+            "C:\\mount",
+            "--url",
+            "http://example.com",
+            "--dir_tree",
+            "dir_tree.json",
+            "--pool-size",
+            "0",
+        ]);
+        assert!(matches.is_err());
+    }
+}
+
+fn memfs_options(matches: &ArgMatches) -> Result<MemFsOptions, Box<dyn Error>> {
+    Ok(MemFsOptions {
+        cache_dir: matches.get_one::<PathBuf>("cache_dir").cloned(),
+        cache_key: matches.get_one::<String>("cache_key").unwrap().parse()?,
+        inject_latency: Duration::from_millis(
+            *matches.get_one::<u64>("inject_latency_ms").unwrap(),
+        ),
+        inject_failure_rate: *matches.get_one::<f64>("inject_failure_rate").unwrap(),
+        inject_seed: *matches.get_one::<u64>("inject_seed").unwrap(),
+        headers: matches
+            .get_many::<(HeaderName, HeaderValue)>("header")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        user_agent: matches.get_one::<String>("user_agent").unwrap().clone(),
+        proxy: matches.get_one::<Url>("proxy").cloned(),
+        no_proxy: matches.get_flag("no_proxy"),
+        deduplicate_streams: matches.get_flag("deduplicate_streams"),
+        read_ahead: *matches.get_one::<u64>("read_ahead_bytes").unwrap(),
+        manifest_only: matches.get_flag("manifest_only"),
+        response_header_timeout: Duration::from_millis(
+            *matches
+                .get_one::<u64>("response_header_timeout_ms")
+                .unwrap(),
+        ),
+        append_ext_from_type: matches.get_flag("append_ext_from_type"),
+        sniff_content_type: matches.get_flag("sniff_content_type"),
+        retries: *matches.get_one::<u32>("retries").unwrap(),
+        retry_base: Duration::from_millis(*matches.get_one::<u64>("retry_base_ms").unwrap()),
+        max_redirects: *matches.get_one::<usize>("max_redirects").unwrap(),
+        warn_large_dir: *matches.get_one::<usize>("warn_large_dir").unwrap(),
+        truncate_large_dir: matches.get_flag("truncate_large_dir"),
+        writable: matches.get_flag("writable"),
+        stable_file_index: matches.get_flag("stable_file_index"),
+        max_name_length: *matches.get_one::<u32>("max_name_length").unwrap(),
+        read_timeout: Duration::from_millis(*matches.get_one::<u64>("read_timeout_ms").unwrap()),
+        poll_interval: Duration::from_millis(*matches.get_one::<u64>("poll_interval_ms").unwrap()),
+        probe_retries: *matches.get_one::<u32>("probe_retries").unwrap(),
+        serve_error_body: matches.get_flag("serve_error_body"),
+        max_bps: *matches.get_one::<u64>("max_bps").unwrap(),
+        synthetic_time: matches
+            .get_one::<u64>("synthetic_time")
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(*secs)),
+        prefetch: *matches.get_one::<usize>("prefetch").unwrap(),
+        no_cache: matches.get_flag("no_cache"),
+        content_length_from: matches
+            .get_one::<String>("content_length_from")
+            .unwrap()
+            .split(',')
+            .map(|source| source.trim().parse())
+            .collect::<Result<_, _>>()?,
+        download_chunks: *matches.get_one::<usize>("download_chunks").unwrap(),
+        auto_create_dirs: matches.get_flag("auto_create_dirs"),
+        url_rewrites: matches
+            .get_many::<(String, String)>("url_rewrite")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        volume_size: *matches.get_one::<u64>("volume_size").unwrap(),
+        free_space: *matches.get_one::<u64>("free_space").unwrap(),
+        volume_label: matches.get_one::<String>("volume_label").unwrap().clone(),
+        serial: *matches.get_one::<u32>("serial").unwrap(),
+        connect_timeout: Duration::from_millis(
+            *matches.get_one::<u64>("connect_timeout_ms").unwrap(),
+        ),
+        request_timeout: Duration::from_millis(
+            *matches.get_one::<u64>("request_timeout_ms").unwrap(),
+        ),
+        stats_file: matches.get_flag("stats_file"),
+        read_only: matches.get_flag("read_only"),
+        cache_persist: matches.get_flag("cache_persist"),
+        index_file: matches.get_one::<String>("index_file").unwrap().clone(),
+        max_path: *matches.get_one::<u32>("max_path").unwrap(),
+        mount_info: !matches.get_flag("no_mount_info"),
+        owner: matches.get_one::<Vec<u8>>("owner").cloned(),
+        everyone_read_only: matches.get_flag("everyone_read_only"),
+        base_path: matches
+            .get_one::<String>("base_path")
+            .cloned()
+            .unwrap_or_default(),
+        strip_prefix: matches
+            .get_one::<String>("strip_prefix")
+            .cloned()
+            .unwrap_or_default(),
+        open_timeout: Duration::from_millis(*matches.get_one::<u64>("timeout_on_open").unwrap()),
+        connect_limit: *matches.get_one::<usize>("connect_limit").unwrap(),
+    })
+}
+
+/// Ignore files looked for in `--ignore-root` when no `--ignore-file` is given.
+const DEFAULT_IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".fsignore"];
+
+/// Build the ignore rules from `files`, or from the default files in `root` when there are none.
+///
+/// An explicitly requested file that can't be read or parsed is an error. The default files are
+/// optional, one that exists but has bad rules is reported and its valid rules still apply.
+fn opt_ignore(
+    enable: bool,
+    root: &Path,
+    files: &[PathBuf],
+) -> Result<Option<Gitignore>, Box<dyn Error>> {
+    if !enable && files.is_empty() {
+        debug!("fs ignore is disabled");
+        return Ok(None);
+    }
+    let mut gitignore_builder = GitignoreBuilder::new(root);
+    if files.is_empty() {
+        for name in DEFAULT_IGNORE_FILES {
+            let file = root.join(name);
+            if !file.is_file() {
+                debug!("no ignore file {}", file.display());
+                continue;
+            }
+            if let Some(e) = gitignore_builder.add(&file) {
+                warn!("failed to load ignore file {}: {e}", file.display());
             }
-            Some(gitignore_builder.build().unwrap())
         }
-        false => {
-            debug!("fs ignore is disabled");
-            None
+    }
+    for file in files {
+        if let Some(e) = gitignore_builder.add(file) {
+            warn!("failed to load ignore file {}: {e}", file.display());
+            return Err(format!("failed to load ignore file {}: {e}", file.display()).into());
         }
     }
+    Ok(Some(gitignore_builder.build()?))
+}
+
+#[test]
+fn test_opt_ignore_files() {
+    let root = std::env::temp_dir().join(format!("opt_ignore_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    // `[` opens a class that never closes, the other rule still applies.
+    std::fs::write(root.join(".gitignore"), "*.log\nbad[\n").unwrap();
+    let ignore = opt_ignore(true, &root, &[]).unwrap().unwrap();
+    assert!(ignore.matched("debug.log", false).is_ignore());
+    // The missing defaults are fine, a missing or broken explicit file is not.
+    assert!(opt_ignore(false, &root, &[root.join("missing")]).is_err());
+    assert!(opt_ignore(false, &root, &[root.join(".gitignore")]).is_err());
+    std::fs::remove_dir_all(&root).unwrap();
 }
 
 #[test]
 fn test_opt_ignore_enabled() {
     env_logger::init();
-    let gitignore = opt_ignore(true);
+    let gitignore = opt_ignore(true, Path::new("."), &[]).unwrap();
     assert!(gitignore.is_some());
     let gitignore = gitignore.unwrap();
     let files_to_check = vec![
@@ -223,20 +1423,28 @@ fn test_opt_ignore_enabled() {
     // Add assertions based on expected behavior of the ignore rules
 }
 
+/// How long downloads get to finish once the file system is unmounted.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::builder().format_timestamp_millis().init();
     let matches = arg_parser();
 
-    let mount_point = U16CString::from_str(matches.get_one::<String>("mount_point").unwrap())?;
+    let mount_point = check_mount_point(matches.get_one::<String>("mount_point").unwrap())?;
+    let mount_point = U16CString::from_str(mount_point)?;
 
     let url = Url::parse(matches.get_one::<String>("url").unwrap()).unwrap();
 
-    let dir_tree_path = matches.get_one::<String>("dir").unwrap();
-    let dir_tree_string = BufReader::new(File::open(dir_tree_path)?);
-    let dir_tree: utils::DirTree = serde_json::from_reader(dir_tree_string)?;
-
-    let ignore = opt_ignore(matches.get_flag("fs_ignore"));
+    let ignore = opt_ignore(
+        matches.get_flag("fs_ignore"),
+        matches.get_one::<PathBuf>("ignore_root").unwrap(),
+        &matches
+            .get_many::<PathBuf>("ignore_file")
+            .unwrap_or_default()
+            .cloned()
+            .collect::<Vec<_>>(),
+    )?;
 
     let mut flags = MountFlags::ALT_STREAM;
     if matches.get_flag("dokan_debug") {
@@ -245,28 +1453,159 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if matches.get_flag("removable") {
         flags |= MountFlags::REMOVABLE;
     }
+    if matches.get_flag("read_only") {
+        flags |= MountFlags::WRITE_PROTECT;
+    }
+    // Windows has no removable network drives, clap rejects asking for both.
+    if matches.get_flag("network_drive") {
+        flags |= MountFlags::NETWORK | MountFlags::ENABLE_UNMOUNT_NETWORK_DRIVE;
+    }
+    let unc_name = matches
+        .get_one::<String>("unc_name")
+        .map(U16CString::from_str)
+        .transpose()?;
 
     let options = MountOptions {
         single_thread: matches.get_flag("single_thread"),
         flags,
+        unc_name: unc_name.as_deref(),
         ..Default::default()
     };
 
-    let thread_pool = Arc::new(ThreadPool::new(20));
+    let pool_size = *matches.get_one::<u64>("pool_size").unwrap();
+    let queue_capacity = *matches.get_one::<usize>("queue_capacity").unwrap();
+    let thread_pool = Arc::new(ThreadPool::with_queue_capacity(
+        pool_size as usize,
+        queue_capacity,
+    ));
     let _thread_pool = Arc::clone(&thread_pool);
-    let handler = MemFsHandler::new(url, thread_pool, ignore);
+    let handler = Arc::new(MemFsHandler::new(
+        url,
+        Arc::clone(&thread_pool),
+        ignore,
+        memfs_options(&matches)?,
+    ));
+
+    let (dir_tree_path, dir_tree) = match matches.get_one::<String>("dir") {
+        Some(path) => (
+            path.clone(),
+            load_dir_tree(&handler.get_client(), path).await?,
+        ),
+        None => {
+            let max_depth = *matches.get_one::<usize>("max_depth").unwrap();
+            let concurrency = *matches.get_one::<usize>("listing_concurrency").unwrap();
+            let dir_tree =
+                crawl::crawl(&handler.get_client(), &handler.url, max_depth, concurrency).await?;
+            (handler.url.to_string(), dir_tree)
+        }
+    };
+
+    dir_tree
+        .validate()
+        .map_err(|e| format!("{dir_tree_path}: {e}"))?;
+
+    if matches.get_flag("verify_structure") {
+        let problems =
+            verify::verify_structure(&handler.get_client(), &handler.url, &dir_tree).await;
+        if !problems.is_empty() {
+            for problem in problems.iter() {
+                eprintln!("{problem}");
+            }
+            return Err(format!(
+                "the server doesn't match {dir_tree_path}: {} differences",
+                problems.len()
+            )
+            .into());
+        }
+    }
+
+    let allowed_hosts = matches
+        .get_many::<String>("allow_host")
+        .map(|hosts| hosts.cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let problems = build_tree(
+        &handler,
+        dir_tree,
+        matches.get_flag("flatten"),
+        &allowed_hosts,
+    );
+    if !problems.is_empty() {
+        for problem in problems.iter() {
+            eprintln!("{problem}");
+        }
+        if !matches.get_flag("allow_invalid_urls") {
+            return Err(format!(
+                "{dir_tree_path} has {} invalid urls, pass --allow-invalid-urls to mount anyway",
+                problems.len()
+            )
+            .into());
+        }
+        warn!("mounting with {} invalid urls", problems.len());
+    }
+
+    if matches.get_flag("check") {
+        let results = handler.check_urls();
+        let mut failed = 0;
+        for (path, result) in results.iter() {
+            match result {
+                Ok(status) => println!("ok      /{path} {status}"),
+                Err(e) => {
+                    failed += 1;
+                    println!("FAILED  /{path}: {e}");
+                }
+            }
+        }
+        println!("Checked {} urls, {failed} failed.", results.len());
+        if failed > 0 {
+            return Err(format!("{failed} of the {} urls failed the check", results.len()).into());
+        }
+        return Ok(());
+    }
+
+    if let Some(globs) = matches.get_many::<String>("preopen") {
+        let mut builder = OverrideBuilder::new("");
+        for glob in globs {
+            builder.add(glob)?;
+        }
+        let pinned = handler.preopen(&builder.build()?);
+        println!("Preopened {pinned} files.");
+    }
+
+    if let Some(port) = matches.get_one::<u16>("reexport_port") {
+        reexport::serve(Arc::clone(&handler), *port)?;
+    }
 
-    build_tree(&handler, dir_tree);
     init();
 
-    let mut mounter = FileSystemMounter::new(&handler, &mount_point, &options);
+    let mut mounter = FileSystemMounter::new(handler.as_ref(), &mount_point, &options);
 
     println!("File system will mount...");
 
-    let file_system = mounter.mount()?;
+    let wait_for_mount_point =
+        Duration::from_secs(*matches.get_one::<u64>("wait_for_mount_point").unwrap());
+    let deadline = Instant::now() + wait_for_mount_point;
+    let file_system = loop {
+        match mounter.mount() {
+            Ok(file_system) => break file_system,
+            Err(e) if mount_point_busy(&e) && Instant::now() < deadline => {
+                warn!("Mount point is busy ({:?}), retrying...", e);
+                std::thread::sleep(Duration::from_millis(500));
+            }
+            Err(e) => {
+                eprintln!(
+                    "Failed to mount {}: {}",
+                    mount_point.to_string_lossy(),
+                    mount_error_message(&e)
+                );
+                shutdown();
+                std::process::exit(1);
+            }
+        }
+    };
 
     // Another thread can unmount the file system.
     let mount_point = mount_point.clone();
+    let _handler = Arc::clone(&handler);
     ctrlc::set_handler(move || {
         if unmount(&mount_point) {
             println!("File system will unmount...")
@@ -276,6 +1615,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "Failed to unmount file system. blocking thread pool:{:}",
                 blocking_num
             );
+            for download in _handler.downloads_in_flight() {
+                let elapsed = download.started_at.elapsed().unwrap_or_default();
+                eprintln!(
+                    "  [{}] {}: {} bytes after {:.1}s",
+                    download.index,
+                    download.url,
+                    download.downloaded,
+                    elapsed.as_secs_f64()
+                );
+            }
+            let abandoned = _thread_pool.shutdown_timeout(SHUTDOWN_TIMEOUT);
+            eprintln!("Exiting, abandoned {abandoned} downloads.");
+            std::process::exit(1);
         }
     })
     .expect("failed to set Ctrl-C handler");
@@ -286,38 +1638,272 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("File system is unmounted.");
 
+    let abandoned = thread_pool.shutdown_timeout(SHUTDOWN_TIMEOUT);
+    if abandoned > 0 {
+        warn!("Abandoned {abandoned} downloads still running after {SHUTDOWN_TIMEOUT:?}");
+    }
+
     shutdown();
 
     Ok(())
 }
 
-fn build_tree(handler: &MemFsHandler, dir_tree: utils::DirTree) {
+/// Accept a drive letter like `Z:` as it is, anything else must be an empty directory and is
+/// made absolute, Dokan doesn't resolve relative mount points.
+fn check_mount_point(mount_point: &str) -> Result<String, String> {
+    let bytes = mount_point.as_bytes();
+    let drive_letter =
+        matches!(bytes, [letter, b':'] | [letter, b':', b'\\'] if letter.is_ascii_alphabetic());
+    if drive_letter {
+        return Ok(mount_point.to_string());
+    }
+    let dir = std::path::absolute(mount_point)
+        .map_err(|e| format!("invalid mount point {mount_point:?}: {e}"))?;
+    let mut entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) if !dir.exists() => {
+            return Err(format!(
+                "mount point {dir:?} doesn't exist, create an empty directory there first"
+            ))
+        }
+        Err(_) if !dir.is_dir() => return Err(format!("mount point {dir:?} is not a directory")),
+        Err(e) => return Err(format!("mount point {dir:?} can't be listed: {e}")),
+    };
+    if entries.next().is_some() {
+        return Err(format!("mount point {dir:?} is not empty"));
+    }
+    Ok(dir.to_string_lossy().into_owned())
+}
+
+/// What to tell the user about a failed mount, the Debug form of the error only names the variant.
+/// Whether `--wait-for-mount-point` should retry after `e`. Only a mount point in use can free up,
+/// an invalid one stays invalid.
+fn mount_point_busy(e: &FileSystemMountError) -> bool {
+    matches!(e, FileSystemMountError::Mount)
+}
+
+fn mount_error_message(e: &FileSystemMountError) -> &'static str {
+    match e {
+        FileSystemMountError::General => {
+            "Dokan reported a general error, run with --dokan-debug for details."
+        }
+        FileSystemMountError::DriveLetter => {
+            "invalid drive letter, use a free letter like Z: or an empty NTFS directory."
+        }
+        FileSystemMountError::DriverInstall => {
+            "the Dokan driver is not installed, install Dokan 2 from https://github.com/dokan-dev/dokany/releases."
+        }
+        FileSystemMountError::Start => {
+            "the Dokan driver failed to start, check that its service is running or reboot after installing it."
+        }
+        FileSystemMountError::Mount => {
+            "the mount point is already in use by another drive or volume, pick another one or unmount it."
+        }
+        FileSystemMountError::MountPoint => {
+            "invalid mount point, use a drive letter like Z: or an empty NTFS directory."
+        }
+        FileSystemMountError::Version => {
+            "the installed Dokan library doesn't match the version this program was built for, install Dokan 2."
+        }
+    }
+}
+
+/// Read the dir tree from a local file, or download it when `source` is an http(s) URL.
+async fn load_dir_tree(client: &Client, source: &str) -> Result<utils::DirTree, Box<dyn Error>> {
+    let (path, data) = match Url::parse(source) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+            debug!("downloading dir tree from {url}");
+            let path = url.path().to_string();
+            let response = client.get(url).send().await?.error_for_status()?;
+            (path, response.bytes().await?.to_vec())
+        }
+        _ => (source.to_string(), std::fs::read(source)?),
+    };
+    let extension = Path::new(&path).extension().and_then(|e| e.to_str());
+    Ok(parse_dir_tree(source, extension, &data)?)
+}
+
+/// Parse a dir tree in the format of its file extension: `.yaml`/`.yml`, `.toml`, anything else
+/// is JSON. Errors point to the place in `source` where it went wrong.
+fn parse_dir_tree(
+    source: &str,
+    extension: Option<&str>,
+    data: &[u8],
+) -> Result<utils::DirTree, String> {
+    match extension.map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("yaml" | "yml") => serde_yaml::from_slice(data).map_err(|e| match e.location() {
+            Some(at) => format!("{source}:{}:{}: invalid YAML: {e}", at.line(), at.column()),
+            None => format!("{source}: invalid YAML: {e}"),
+        }),
+        Some("toml") => {
+            let text = std::str::from_utf8(data)
+                .map_err(|e| format!("{source}: invalid TOML: not UTF-8: {e}"))?;
+            toml::from_str(text).map_err(|e| match e.span() {
+                Some(span) => {
+                    let before = &text[..span.start];
+                    let line = before.matches('\n').count() + 1;
+                    let column = before.len() - before.rfind('\n').map_or(0, |n| n + 1) + 1;
+                    format!("{source}:{line}:{column}: invalid TOML: {}", e.message())
+                }
+                None => format!("{source}: invalid TOML: {}", e.message()),
+            })
+        }
+        _ => serde_json::from_slice(data).map_err(|e| {
+            let what = match e.classify() {
+                Category::Io => "could not read",
+                Category::Syntax => "malformed JSON",
+                Category::Data => "invalid dir tree",
+                Category::Eof => "truncated JSON",
+            };
+            format!("{source}:{}:{}: {what}: {e}", e.line(), e.column())
+        }),
+    }
+}
+
+/// `name`, or `name (2)`, `name (3)`... before the extension when `children` already has it.
+fn unique_name(children: &HashMap<EntryName, Arc<Entry>>, name: &str) -> EntryName {
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    };
+    let mut candidate = U16String::from_str(name);
+    let mut n = 1;
+    while children.contains_key(EntryNameRef::new(&candidate)) {
+        n += 1;
+        candidate = U16String::from_str(&format!("{stem} ({n}){ext}"));
+    }
+    EntryName(candidate)
+}
+
+/// Create the entry of a dir tree file with the metadata it declares.
+///
+/// It is fetched from its `url` when one is given, otherwise from `path` below `--url` like any
+/// other mount path. A url that doesn't resolve is added to `problems` and the entry falls back to
+/// its path.
+fn http_file_entry(
+    handler: &MemFsHandler,
+    file: &utils::DirTree,
+    path: &str,
+    mut stat: Stat,
+    allowed_hosts: &[String],
+    problems: &mut Vec<String>,
+) -> HttpFileEntry {
+    if let Some(mtime) = file.mtime {
+        stat.mtime = UNIX_EPOCH + Duration::from_secs(mtime);
+        stat.ctime = stat.mtime;
+    }
+    let mut entry = HttpFileEntry::new(stat);
+    *entry.times_pinned.write().unwrap() = file.mtime.is_some();
+    entry.sha256 = file.sha256.clone();
+    match &file.url {
+        Some(url) => match resolve_url(&handler.url, url, allowed_hosts) {
+            Ok(url) => *entry.url.write().unwrap() = Some(url),
+            Err(e) => problems.push(format!("/{path}: invalid url {url:?}: {e}")),
+        },
+        None => *entry.url.write().unwrap() = Some(handler.file_url(path)),
+    }
+    *entry.size.write().unwrap() = file.size;
+    entry
+}
+
+/// Resolve a dir tree url against `--url`. It must be http(s) and, when `allowed_hosts` isn't
+/// empty, point to the `--url` host or one of them.
+fn resolve_url(base: &Url, url: &str, allowed_hosts: &[String]) -> Result<Url, String> {
+    let url = base.join(url).map_err(|e| e.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme `{}`", url.scheme()));
+    }
+    let Some(host) = url.host_str() else {
+        return Err("no host".to_string());
+    };
+    if !allowed_hosts.is_empty()
+        && base.host_str() != Some(host)
+        && !allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    {
+        return Err(format!("host `{host}` is not allowed"));
+    }
+    Ok(url)
+}
+
+/// Add the dir tree below the handler's root and return the problems found in its urls.
+fn build_tree(
+    handler: &MemFsHandler,
+    dir_tree: utils::DirTree,
+    flatten: bool,
+    allowed_hosts: &[String],
+) -> Vec<String> {
+    let mut problems = Vec::new();
     let root = &handler.root;
-    let mut stack = vec![(Arc::clone(&root), dir_tree)];
-    while let Some((parent, dir_tree)) = stack.pop() {
+    let mut stack = vec![(Arc::clone(&root), dir_tree, String::new())];
+    while let Some((parent, dir_tree, path)) = stack.pop() {
+        // Locked once per directory rather than per entry, nothing else reads the tree yet.
+        let mut children = parent.children.write().unwrap();
         for child in dir_tree.children {
+            let child_path = format!("{path}{}", child.name);
             let child_stat = Stat::new(
                 handler.next_id(),
                 0,
-                SecurityDescriptor::new_default().unwrap(),
+                handler.default_security(),
                 Arc::downgrade(&parent),
             );
+            if flatten {
+                if child.is_folder() {
+                    stack.push((Arc::clone(&parent), child, child_path));
+                } else {
+                    let file = http_file_entry(
+                        handler,
+                        &child,
+                        &child_path,
+                        child_stat,
+                        allowed_hosts,
+                        &mut problems,
+                    );
+                    let name = unique_name(&children, &child.name);
+                    children.insert(name, Arc::new(Entry::HttpFile(Arc::new(file))));
+                }
+                continue;
+            }
+            let name = child.name.replace("/", "");
             let child_entry = match child.is_folder() {
                 true => {
-                    let dir_entry = Arc::new(DirEntry::new(child_stat));
-                    stack.push((Arc::clone(&dir_entry), child.clone()));
-                    Ok(Entry::Directory(dir_entry))
+                    let time = match child.mtime {
+                        Some(mtime) => UNIX_EPOCH + Duration::from_secs(mtime),
+                        None => handler.synthetic_time(),
+                    };
+                    let dir_entry = DirEntry::new(child_stat.with_times(time));
+                    let dir_entry = Arc::new(match &child.link {
+                        Some(link) => dir_entry.with_link(link.clone()),
+                        None => dir_entry,
+                    });
+                    // An alias shows the children of its target, not its own.
+                    if child.link.is_none() {
+                        stack.push((Arc::clone(&dir_entry), child, child_path));
+                    }
+                    Entry::Directory(dir_entry)
+                }
+                false => {
+                    let file = http_file_entry(
+                        handler,
+                        &child,
+                        &child_path,
+                        child_stat,
+                        allowed_hosts,
+                        &mut problems,
+                    );
+                    Entry::HttpFile(Arc::new(file))
                 }
-                // false => Entry::HttpFile(Arc::new(HttpFileEntry::new(
-                //     child_stat,
-                //     url.join(), // FIXME:
-                // ))),
-                false => Err("TODO: not supported file yet"),
             };
-            parent.children.write().unwrap().insert(
-                EntryName(U16String::from_str(&child.name.replace("/", ""))),
-                Arc::new(child_entry.unwrap()),
-            );
+            // Names only differing in case would replace each other, lookups ignore case.
+            let key = unique_name(&children, &name);
+            if key.0.to_string_lossy() != name {
+                warn!(
+                    "/{path}{name}: another entry has the same name ignoring case, showing it as {:?}",
+                    key.0.to_string_lossy()
+                );
+            }
+            children.insert(key, Arc::new(child_entry));
         }
     }
     fn print_tree(entry: &Arc<DirEntry>, prefix: String) {
@@ -339,6 +1925,8 @@ fn build_tree(handler: &MemFsHandler, dir_tree: utils::DirTree) {
         }
     }
 
-    print_tree(&root, String::new());
-    // root
+    if log::log_enabled!(log::Level::Debug) {
+        print_tree(&root, String::new());
+    }
+    problems
 }
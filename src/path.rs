@@ -11,6 +11,22 @@ use crate::fs::{
 
 // Use the same value as NTFS.
 pub const MAX_COMPONENT_LENGTH: u32 = 255;
+// Use the same value as Win32 `MAX_PATH`.
+pub const MAX_PATH_LENGTH: u32 = 260;
+
+/// Reject a path component with a file or stream name longer than `max`.
+///
+/// The two parts of `name:stream:$DATA` are limited separately, like on NTFS.
+pub fn check_name_length(name: &U16Str, max: usize) -> OperationResult<()> {
+    let mut parts = name.as_slice().split(|x| *x == ':' as u16);
+    let file_name = parts.next().unwrap_or_default();
+    let stream_name = parts.next().unwrap_or_default();
+    if file_name.len() > max || stream_name.len() > max {
+        Err(STATUS_OBJECT_NAME_INVALID)
+    } else {
+        Ok(())
+    }
+}
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum StreamType {
@@ -109,53 +125,30 @@ fn find_dir_entry(
     path: &[&U16Str],
 ) -> OperationResult<Arc<DirEntry>> {
     if let Some(name) = path.get(0) {
-        if name.len() > MAX_COMPONENT_LENGTH as usize {
-            return Err(STATUS_OBJECT_NAME_INVALID);
-        }
+        check_name_length(name, handler.max_name_length())?;
         let children = cur_entry.children.read().unwrap();
         let child_entry = if let Some(a) = children.get(EntryNameRef::new(name)) {
-            a
+            Arc::clone(a)
         } else {
             std::mem::drop(children);
-            // let parent = cur_entry.stat.read().unwrap().parent.upgrade();
-            // let key = if let Some(parent1) = parent {
-            //     let parent_children = parent1.children.read().unwrap();
-            //     log::debug!("parent_children = {:?}", parent_children);
-            //     let ret = parent_children.iter().find_map(|(k, v)| {
-            //         let dir_entry = match v.as_ref() {
-            //             Entry::Directory(dir_entry) => dir_entry,
-            //             _ => return None,
-            //         };
-            //         if Arc::ptr_eq(dir_entry, cur_entry) {
-            //             Some(k)
-            //         } else {
-            //             None
-            //         }
-            //     });
-            //     match ret {
-            //         Some(name) => {
-            //             let name_string = name.0.to_string().unwrap();
-            //             // Store the string in a variable so it lives long enough
-            //             Box::leak(name_string.into_boxed_str())
-            //         }
-            //         None => "",
-            //     }
-            // } else {
-            //     "(can not find parent)"
-            // };
+            if !handler.auto_create_dirs() {
+                log::debug!("find_dir_entry: {} not found", name.to_string_lossy());
+                return Err(STATUS_OBJECT_PATH_NOT_FOUND);
+            }
             log::warn!(
-                "find_dir_entry: {} not found",
-                name.to_string_lossy(),
-                // key // .to_string_lossy()
+                "find_dir_entry: {} not found, creating it",
+                name.to_string_lossy()
             );
             {
                 let mut _children = cur_entry.children.write().unwrap();
-                &handler.create_dir_entry(index, cur_entry, &mut _children, name.to_ustring())
+                handler.create_dir_entry(index, cur_entry, &mut _children, name.to_ustring())
             }
-            // return Err(STATUS_OBJECT_PATH_NOT_FOUND);
         };
         match child_entry.as_ref() {
-            Entry::Directory(dir) => find_dir_entry(index, handler, dir, &path[1..]),
+            Entry::Directory(dir) => {
+                let dir = resolve_link(handler, dir)?;
+                find_dir_entry(index, handler, &dir, &path[1..])
+            }
             _ => Err(STATUS_OBJECT_PATH_NOT_FOUND),
         }
     } else {
@@ -163,6 +156,45 @@ fn find_dir_entry(
     }
 }
 
+/// Follow the `link` of `dir` to the directory it stands for, through any further links. A link
+/// that needs itself to resolve fails with `STATUS_REPARSE_POINT_NOT_RESOLVED`.
+pub fn resolve_link(handler: &MemFsHandler, dir: &Arc<DirEntry>) -> OperationResult<Arc<DirEntry>> {
+    resolve_link_chain(handler, dir, &mut Vec::new())
+}
+
+fn resolve_link_chain(
+    handler: &MemFsHandler,
+    dir: &Arc<DirEntry>,
+    chain: &mut Vec<*const DirEntry>,
+) -> OperationResult<Arc<DirEntry>> {
+    let Some(link) = &dir.link else {
+        return Ok(Arc::clone(dir));
+    };
+    if chain.contains(&Arc::as_ptr(dir)) {
+        log::warn!("resolve_link: {link} leads back to itself");
+        return Err(STATUS_REPARSE_POINT_NOT_RESOLVED);
+    }
+    chain.push(Arc::as_ptr(dir));
+    let mut cur = Arc::clone(&handler.root);
+    for name in link.split(['/', '\\']).filter(|s| !s.is_empty()) {
+        let child = cur
+            .children
+            .read()
+            .unwrap()
+            .get(EntryNameRef::new(&U16String::from_str(name)))
+            .cloned();
+        cur = match child.as_deref() {
+            Some(Entry::Directory(next)) => resolve_link_chain(handler, next, chain)?,
+            _ => {
+                log::debug!("resolve_link: {link} not found");
+                return Err(STATUS_OBJECT_PATH_NOT_FOUND);
+            }
+        };
+    }
+    chain.pop();
+    Ok(cur)
+}
+
 pub fn split_path<'a>(
     index: u64,
     handler: &MemFsHandler,
@@ -170,6 +202,14 @@ pub fn split_path<'a>(
     path: &'a U16CStr,
 ) -> OperationResult<Option<(FullName<'a>, Arc<DirEntry>)>> {
     let root = &handler.root;
+    if path.len() > handler.max_path() {
+        log::debug!(
+            "split_path: {} characters is longer than {}",
+            path.len(),
+            handler.max_path()
+        );
+        return Err(STATUS_NAME_TOO_LONG);
+    }
     let path = path
         .as_slice()
         .split(|x| *x == '\\' as u16)
@@ -181,12 +221,156 @@ pub fn split_path<'a>(
         Ok(None)
     } else {
         let name = *path.iter().last().unwrap();
-        if name.len() > MAX_COMPONENT_LENGTH as usize {
-            return Err(STATUS_OBJECT_NAME_INVALID);
-        }
+        check_name_length(name, handler.max_name_length())?;
         Ok(Some((
             FullName::new(name)?,
             find_dir_entry(index, handler, root, &path[..path.len() - 1])?,
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fs::{handler::MemFsOptions, metadata::Stat},
+        security::SecurityDescriptor,
+        thread_pool::ThreadPool,
+    };
+    use url::Url;
+    use widestring::U16CString;
+
+    #[test]
+    fn test_check_name_length() {
+        let check = |name: String| check_name_length(&U16String::from_str(&name), 8);
+        assert!(check("a".repeat(8)).is_ok());
+        assert!(check("a".repeat(9)).is_err());
+        assert!(check(format!("{}:{}", "a".repeat(8), "s".repeat(8))).is_ok());
+        assert!(check(format!("{}:{}:$DATA", "a".repeat(8), "s".repeat(8))).is_ok());
+        assert!(check(format!("{}:{}", "a".repeat(8), "s".repeat(9))).is_err());
+        assert!(check(format!("{}:mime", "a".repeat(9))).is_err());
+    }
+
+    #[test]
+    fn test_max_path() {
+        let handler = |max_path| {
+            MemFsHandler::new(
+                Url::parse("http://127.0.0.1/").unwrap(),
+                Arc::new(ThreadPool::new(1)),
+                None,
+                MemFsOptions {
+                    max_path,
+                    ..Default::default()
+                },
+            )
+        };
+        // `\` and a file name, so only the total length can fail.
+        let split = |handler: &MemFsHandler, len: usize| {
+            let path = U16CString::from_str(format!("\\{}", "a".repeat(len - 1))).unwrap();
+            split_path(0, handler, &path).err()
+        };
+
+        let default = handler(0);
+        assert_eq!(split(&default, 255), None);
+        let nested = U16CString::from_str(format!("{}\\a", "\\b".repeat(129))).unwrap();
+        assert_eq!(nested.len(), 260);
+        assert_eq!(
+            split_path(0, &default, &nested).err(),
+            Some(STATUS_OBJECT_PATH_NOT_FOUND)
+        );
+        let nested = U16CString::from_str(format!("{}\\aa", "\\b".repeat(129))).unwrap();
+        assert_eq!(
+            split_path(0, &default, &nested).err(),
+            Some(STATUS_NAME_TOO_LONG)
+        );
+
+        let short = handler(16);
+        assert_eq!(split(&short, 16), None);
+        assert_eq!(split(&short, 17), Some(STATUS_NAME_TOO_LONG));
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        let handler = MemFsHandler::new(
+            Url::parse("http://127.0.0.1/").unwrap(),
+            Arc::new(ThreadPool::new(1)),
+            None,
+            MemFsOptions::default(),
+        );
+        let add = |parent: &Arc<DirEntry>, name: &str, link: Option<&str>| {
+            let stat = Stat::new(
+                0,
+                0,
+                SecurityDescriptor::new_default().unwrap(),
+                Arc::downgrade(parent),
+            );
+            let dir = DirEntry::new(stat);
+            let dir = Arc::new(match link {
+                Some(link) => dir.with_link(link.to_string()),
+                None => dir,
+            });
+            parent.children.write().unwrap().insert(
+                EntryName(U16String::from_str(name)),
+                Arc::new(Entry::Directory(Arc::clone(&dir))),
+            );
+            dir
+        };
+        let v2 = add(&handler.root, "v2.3", None);
+        let docs = add(&v2, "docs", None);
+        add(&handler.root, "latest", Some("v2.3/"));
+        add(&handler.root, "stable", Some("/latest"));
+        add(&handler.root, "ping", Some("pong"));
+        add(&handler.root, "pong", Some("ping/"));
+        add(&docs, "up", Some("/stable/docs"));
+        add(&handler.root, "gone", Some("v1/"));
+
+        let parent = |path: &str| {
+            let path = U16CString::from_str(path).unwrap();
+            split_path(0, &handler, &path).map(|found| found.unwrap().1)
+        };
+        assert!(Arc::ptr_eq(&parent("\\latest\\a.txt").unwrap(), &v2));
+        assert!(Arc::ptr_eq(
+            &parent("\\stable\\docs\\a.txt").unwrap(),
+            &docs
+        ));
+        assert!(Arc::ptr_eq(
+            &parent("\\LATEST\\docs\\up\\a.txt").unwrap(),
+            &docs
+        ));
+        assert_eq!(
+            parent("\\ping\\a.txt").err(),
+            Some(STATUS_REPARSE_POINT_NOT_RESOLVED)
+        );
+        assert_eq!(
+            parent("\\gone\\a.txt").err(),
+            Some(STATUS_OBJECT_PATH_NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn test_missing_dirs_are_not_created() {
+        let handler = |auto_create_dirs| {
+            MemFsHandler::new(
+                Url::parse("http://127.0.0.1/").unwrap(),
+                Arc::new(ThreadPool::new(1)),
+                None,
+                MemFsOptions {
+                    auto_create_dirs,
+                    ..Default::default()
+                },
+            )
+        };
+        let path = U16CString::from_str("\\no\\such\\dir\\file.txt").unwrap();
+
+        let strict = handler(false);
+        assert_eq!(
+            split_path(0, &strict, &path).err(),
+            Some(STATUS_OBJECT_PATH_NOT_FOUND)
+        );
+        assert!(strict.root.children.read().unwrap().is_empty());
+
+        let creating = handler(true);
+        assert!(split_path(0, &creating, &path).is_ok());
+        assert_eq!(creating.root.children.read().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use dokan::OperationResult;
+use log::{debug, info, warn};
+use percent_encoding::percent_decode_str;
+use widestring::U16String;
+use winapi::shared::{ntdef::NTSTATUS, ntstatus::*};
+
+use crate::fs::{
+    entry::{Entry, EntryNameRef},
+    handler::{http_to_status, MemFsHandler},
+};
+
+/// Serve the in-memory tree read-only at `http://127.0.0.1:{port}/`.
+///
+/// The listener runs on a background thread for the rest of the process lifetime.
+pub fn serve(handler: Arc<MemFsHandler>, port: u16) -> std::io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Re-exporting mounted content at http://127.0.0.1:{port}/");
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let handler = Arc::clone(&handler);
+                    thread::spawn(move || {
+                        if let Err(e) = handle_connection(&handler, stream) {
+                            debug!("reexport: connection error: {e}");
+                        }
+                    });
+                }
+                Err(e) => warn!("reexport: failed to accept connection: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(handler: &MemFsHandler, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the request headers, we don't use any of them.
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("/");
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    info!("reexport: {method} {path}");
+
+    if method != "GET" && method != "HEAD" {
+        return write_response(
+            &mut stream,
+            405,
+            "Method Not Allowed",
+            b"",
+            method == "HEAD",
+        );
+    }
+    match read_path(handler, &path) {
+        Ok(data) => write_response(&mut stream, 200, "OK", &data, method == "HEAD"),
+        Err(status) => {
+            let (code, reason) = status_to_http(status);
+            write_response(
+                &mut stream,
+                code,
+                reason,
+                reason.as_bytes(),
+                method == "HEAD",
+            )
+        }
+    }
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    code: u16,
+    reason: &str,
+    body: &[u8],
+    head_only: bool,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {code} {reason}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    if !head_only {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
+
+fn status_to_http(status: NTSTATUS) -> (u16, &'static str) {
+    match status {
+        STATUS_OBJECT_NAME_NOT_FOUND | STATUS_OBJECT_PATH_NOT_FOUND => (404, "Not Found"),
+        STATUS_ACCESS_DENIED => (403, "Forbidden"),
+        STATUS_IO_TIMEOUT => (504, "Gateway Timeout"),
+        _ => (500, "Internal Server Error"),
+    }
+}
+
+/// Resolve `path` against the tree and return the file bytes, downloading them like `create_file` would.
+fn read_path(handler: &MemFsHandler, path: &str) -> OperationResult<Vec<u8>> {
+    // `file_url` splits on both separators, so must the check below.
+    let components = path
+        .split(['\\', '/'])
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    // The tree has no such entries, and the server must not be asked to walk out of the base url.
+    if components.iter().any(|&s| s == "." || s == "..") {
+        return Err(STATUS_OBJECT_NAME_NOT_FOUND);
+    }
+    if let Some(ignore) = &handler.ignore {
+        if ignore.matched(components.join("/"), false).is_ignore() {
+            return Err(STATUS_ACCESS_DENIED);
+        }
+    }
+
+    let mut cur = Entry::Directory(Arc::clone(&handler.root));
+    for name in components.iter() {
+        let child = match &cur {
+            Entry::Directory(dir) => dir
+                .children
+                .read()
+                .unwrap()
+                .get(EntryNameRef::new(&U16String::from_str(name)))
+                .map(|e| e.as_ref().clone()),
+            _ => return Err(STATUS_OBJECT_PATH_NOT_FOUND),
+        };
+        match child {
+            Some(entry) => cur = entry,
+            // Not part of the tree yet, let the server decide.
+            None => return download(handler, &components.join("/")),
+        }
+    }
+    match &cur {
+        Entry::File(file) => Ok(file.data.read().unwrap().clone()),
+        Entry::HttpFile(_) => download(handler, &components.join("/")),
+        Entry::Directory(_) if components.is_empty() => download(handler, ""),
+        Entry::Directory(_) => download(handler, &format!("{}/", components.join("/"))),
+    }
+}
+
+fn download(handler: &MemFsHandler, name: &str) -> OperationResult<Vec<u8>> {
+    let name = if name.is_empty() || name.ends_with('/') {
+        format!("{name}{}", handler.index_file())
+    } else {
+        name.to_string()
+    };
+    let url = handler.file_url(&name);
+    let done = Arc::new(AtomicBool::new(false));
+    let _done = Arc::clone(&done);
+    let index = handler.next_id();
+    let stream = handler
+        .create_new_http_stream(
+            index,
+            url,
+            &name,
+            true,
+            None,
+            Some(Box::new(move || _done.store(true, Ordering::Release))),
+        )
+        .ok_or(STATUS_UNEXPECTED_IO_ERROR)?;
+    let wake = Arc::clone(&stream.read().unwrap().progress);
+    handler.wait_for_download(
+        || !done.load(Ordering::Acquire),
+        || stream.read().unwrap().downloaded_len(),
+        Some(&wake),
+        Some(|| {
+            warn!("reexport: [{index}] {name} stalled past --read-timeout-ms");
+            Err(STATUS_IO_TIMEOUT)
+        }),
+    )?;
+    let stream = stream.read().unwrap();
+    let data = stream.contents().map_err(|_| STATUS_UNEXPECTED_IO_ERROR)?;
+    match stream.status {
+        // A failed download keeps no body, answer with the server's error rather than an empty file.
+        Some(status) if status >= 400 && data.is_empty() => Err(http_to_status(status)),
+        _ => Ok(data),
+    }
+}
@@ -52,72 +52,99 @@ fn get_well_known_sid(sid_type: winnt::WELL_KNOWN_SID_TYPE) -> OperationResult<B
 	}
 }
 
-fn create_default_dacl() -> OperationResult<Box<[u8]>> {
-	unsafe {
-		let admins_sid = get_well_known_sid(winnt::WinBuiltinAdministratorsSid)?;
-		let system_sid = get_well_known_sid(winnt::WinLocalSystemSid)?;
-		let auth_sid = get_well_known_sid(winnt::WinAuthenticatedUserSid)?;
-		let users_sid = get_well_known_sid(winnt::WinBuiltinUsersSid)?;
-
-		let acl_len = mem::size_of::<winnt::ACL>()
-			+ (mem::size_of::<winnt::ACCESS_ALLOWED_ACE>() - mem::size_of::<u32>()) * 4
-			+ admins_sid.len()
-			+ system_sid.len()
-			+ auth_sid.len()
-			+ users_sid.len();
-		let mut acl = vec![0u8; acl_len].into_boxed_slice();
-		win32_ensure(
-			securitybaseapi::InitializeAcl(
-				acl.as_mut_ptr() as winnt::PACL,
-				acl_len as u32,
-				winnt::ACL_REVISION as u32,
-			) == minwindef::TRUE,
-		)?;
+/// Parse a SID in its string form, like `S-1-5-32-544`, into the binary layout Windows uses.
+pub fn parse_sid(s: &str) -> Result<Vec<u8>, String> {
+	let invalid = || format!("`{s}` is not a SID like S-1-5-32-544");
+	let mut parts = s.split('-');
+	if !parts.next().is_some_and(|p| p.eq_ignore_ascii_case("S")) {
+		return Err(invalid());
+	}
+	if parts.next() != Some("1") {
+		return Err(invalid());
+	}
+	let authority = parts
+		.next()
+		.and_then(|p| match p.strip_prefix("0x").or_else(|| p.strip_prefix("0X")) {
+			Some(hex) => u64::from_str_radix(hex, 16).ok(),
+			None => p.parse::<u64>().ok(),
+		})
+		.filter(|a| *a < 1 << 48)
+		.ok_or_else(invalid)?;
+	let sub_authorities = parts
+		.map(|p| p.parse::<u32>())
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|_| invalid())?;
+	if sub_authorities.len() > winnt::SID_MAX_SUB_AUTHORITIES as usize {
+		return Err(invalid());
+	}
+	let mut sid = vec![1, sub_authorities.len() as u8];
+	sid.extend_from_slice(&authority.to_be_bytes()[2..]);
+	for sub_authority in sub_authorities {
+		sid.extend_from_slice(&sub_authority.to_le_bytes());
+	}
+	Ok(sid)
+}
 
-		let flags = (winnt::CONTAINER_INHERIT_ACE | winnt::OBJECT_INHERIT_ACE) as u32;
-		win32_ensure(
-			securitybaseapi::AddAccessAllowedAceEx(
-				acl.as_mut_ptr() as winnt::PACL,
-				winnt::ACL_REVISION as u32,
-				flags,
+fn create_default_dacl(everyone_read_only: bool) -> OperationResult<Box<[u8]>> {
+	unsafe {
+		let mut aces = vec![
+			(
+				get_well_known_sid(winnt::WinBuiltinAdministratorsSid)?,
 				winnt::FILE_ALL_ACCESS,
-				admins_sid.as_ptr() as winnt::PSID,
-			) == minwindef::TRUE,
-		)?;
-
-		win32_ensure(
-			securitybaseapi::AddAccessAllowedAceEx(
-				acl.as_mut_ptr() as winnt::PACL,
-				winnt::ACL_REVISION as u32,
-				flags,
+			),
+			(
+				get_well_known_sid(winnt::WinLocalSystemSid)?,
 				winnt::FILE_ALL_ACCESS,
-				system_sid.as_ptr() as winnt::PSID,
-			) == minwindef::TRUE,
-		)?;
-
-		win32_ensure(
-			securitybaseapi::AddAccessAllowedAceEx(
-				acl.as_mut_ptr() as winnt::PACL,
-				winnt::ACL_REVISION as u32,
-				flags,
+			),
+		];
+		if everyone_read_only {
+			aces.push((
+				get_well_known_sid(winnt::WinWorldSid)?,
+				winnt::FILE_GENERIC_READ | winnt::FILE_GENERIC_EXECUTE,
+			));
+		} else {
+			aces.push((
+				get_well_known_sid(winnt::WinAuthenticatedUserSid)?,
 				winnt::FILE_GENERIC_READ
 					| winnt::FILE_GENERIC_WRITE
 					| winnt::FILE_GENERIC_EXECUTE
 					| winnt::DELETE,
-				auth_sid.as_ptr() as winnt::PSID,
-			) == minwindef::TRUE,
-		)?;
+			));
+			aces.push((
+				get_well_known_sid(winnt::WinBuiltinUsersSid)?,
+				winnt::FILE_GENERIC_READ | winnt::FILE_GENERIC_EXECUTE,
+			));
+		}
 
+		let acl_len = mem::size_of::<winnt::ACL>()
+			+ aces
+				.iter()
+				.map(|(sid, _)| {
+					mem::size_of::<winnt::ACCESS_ALLOWED_ACE>() - mem::size_of::<u32>() + sid.len()
+				})
+				.sum::<usize>();
+		let mut acl = vec![0u8; acl_len].into_boxed_slice();
 		win32_ensure(
-			securitybaseapi::AddAccessAllowedAceEx(
+			securitybaseapi::InitializeAcl(
 				acl.as_mut_ptr() as winnt::PACL,
+				acl_len as u32,
 				winnt::ACL_REVISION as u32,
-				flags,
-				winnt::FILE_GENERIC_READ | winnt::FILE_GENERIC_EXECUTE,
-				users_sid.as_ptr() as winnt::PSID,
 			) == minwindef::TRUE,
 		)?;
 
+		let flags = (winnt::CONTAINER_INHERIT_ACE | winnt::OBJECT_INHERIT_ACE) as u32;
+		for (sid, access) in aces.iter() {
+			win32_ensure(
+				securitybaseapi::AddAccessAllowedAceEx(
+					acl.as_mut_ptr() as winnt::PACL,
+					winnt::ACL_REVISION as u32,
+					flags,
+					*access,
+					sid.as_ptr() as winnt::PSID,
+				) == minwindef::TRUE,
+			)?;
+		}
+
 		Ok(acl)
 	}
 }
@@ -169,10 +196,20 @@ impl SecurityDescriptor {
 		}
 	}
 
+	#[allow(unused)]
 	pub fn new_default() -> OperationResult<Self> {
-		let owner_sid = Pin::new(get_well_known_sid(winnt::WinLocalSystemSid)?);
+		Self::new(None, false)
+	}
+
+	/// A descriptor owned by `owner`, a binary SID from `parse_sid`, or the local system. With
+	/// `everyone_read_only` nobody but administrators and the system can change entries.
+	pub fn new(owner: Option<&[u8]>, everyone_read_only: bool) -> OperationResult<Self> {
+		let owner_sid = Pin::new(match owner {
+			Some(owner) => owner.to_vec().into_boxed_slice(),
+			None => get_well_known_sid(winnt::WinLocalSystemSid)?,
+		});
 		let group_sid = Pin::new(get_well_known_sid(winnt::WinLocalSystemSid)?);
-		let dacl = Pin::new(create_default_dacl()?);
+		let dacl = Pin::new(create_default_dacl(everyone_read_only)?);
 
 		unsafe {
 			let mut abs_desc = mem::zeroed::<winnt::SECURITY_DESCRIPTOR>();
@@ -1,18 +1,53 @@
 #![allow(unused)]
 
-use log::debug;
+use log::{debug, error};
 use std::{
+    any::Any,
     future::Future,
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
-    sync::{mpsc, Arc, Mutex, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
     thread,
+    time::{Duration, Instant},
 };
 use tokio::runtime::Runtime;
 
 #[derive(Debug)]
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    workers: Mutex<Vec<Worker>>,
+    sender: Mutex<Option<mpsc::SyncSender<Job>>>,
+    /// Jobs submitted and not finished yet, queued or running.
+    pending: Arc<AtomicUsize>,
+    working: WorkCounter,
+}
+
+/// Counts jobs and the tasks they spawn while they run, see `ThreadPool::work_counter`.
+#[derive(Debug, Clone, Default)]
+pub struct WorkCounter(Arc<AtomicUsize>);
+
+impl WorkCounter {
+    /// Counts as working until the returned guard is dropped.
+    pub fn start(&self) -> Working {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Working(Arc::clone(&self.0))
+    }
+
+    fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// One job or task counted by a `WorkCounter`.
+#[derive(Debug)]
+pub struct Working(Arc<AtomicUsize>);
+
+impl Drop for Working {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 type SyncFunction = dyn FnOnce() + Send + 'static;
@@ -31,6 +66,10 @@ enum Job {
 }
 
 impl ThreadPool {
+    /// Jobs that can wait for a worker before `execute` blocks, unless set with
+    /// `with_queue_capacity`.
+    pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
     /// Create a new ThreadPool.
     ///
     /// The size is the number of threads in the pool.
@@ -39,56 +78,159 @@ impl ThreadPool {
     ///
     /// The `new` function will panic if the size is zero.
     pub fn new(size: usize) -> ThreadPool {
+        Self::with_queue_capacity(size, Self::DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// Create a new ThreadPool where at most `capacity` jobs wait for a worker, further jobs block
+    /// the caller until one is picked up. With 0 every job waits for an idle worker.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size is zero.
+    pub fn with_queue_capacity(size: usize, capacity: usize) -> ThreadPool {
         assert!(size > 0);
 
-        let (sender, receiver) = mpsc::channel();
+        let (sender, receiver) = mpsc::sync_channel(capacity);
 
         let receiver = Arc::new(Mutex::new(receiver));
+        // One runtime drives the async jobs of every worker. Only the workers keep a reference, so the
+        // last one to exit drops it on its own thread rather than inside the caller's async context.
+        let rt = Arc::new(Runtime::new().unwrap());
+        let pending = Arc::new(AtomicUsize::new(0));
+        let working = WorkCounter::default();
 
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(
+                id,
+                Arc::clone(&receiver),
+                Arc::clone(&rt),
+                Arc::clone(&pending),
+                working.clone(),
+            ));
         }
 
         ThreadPool {
-            workers,
-            sender: Some(sender),
+            workers: Mutex::new(workers),
+            sender: Mutex::new(Some(sender)),
+            pending,
+            working,
         }
     }
 
+    fn send(&self, job: Job) {
+        // Cloned so a caller blocked on a full queue doesn't hold up shutdown.
+        let sender = self.sender.lock().unwrap().clone();
+        match sender {
+            Some(sender) => {
+                self.pending.fetch_add(1, Ordering::SeqCst);
+                if sender.send(job).is_err() {
+                    self.pending.fetch_sub(1, Ordering::SeqCst);
+                    debug!("Thread pool shut down while queueing, dropping job.");
+                }
+            }
+            None => debug!("Thread pool is shut down, dropping job."),
+        }
+    }
+
+    fn try_send(&self, job: Job) -> bool {
+        match self.sender.lock().unwrap().as_ref() {
+            Some(sender) => {
+                self.pending.fetch_add(1, Ordering::SeqCst);
+                let sent = sender.try_send(job).is_ok();
+                if !sent {
+                    self.pending.fetch_sub(1, Ordering::SeqCst);
+                }
+                sent
+            }
+            None => false,
+        }
+    }
+
+    /// Run `f` on a worker, waiting for room when the queue is full.
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Job::Sync(Box::new(f));
+        self.send(Job::Sync(Box::new(f)));
+    }
 
-        self.sender.as_ref().unwrap().send(job).unwrap();
+    /// Run `f` on a worker unless the queue is full or the pool is shut down. Returns whether it
+    /// was queued.
+    pub fn try_execute<F>(&self, f: F) -> bool
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.try_send(Job::Sync(Box::new(f)))
     }
 
+    /// Run the future returned by `f` on a worker, waiting for room when the queue is full.
     pub fn execute_async<F>(&self, f: F)
     where
         F: FnOnce() -> Pin<Box<dyn Future<Output = Result<(), reqwest::Error>> + Send>>
             + Send
             + 'static,
     {
-        let job = Job::Async(Box::new(f));
+        self.send(Job::Async(Box::new(f)));
+    }
 
-        self.sender.as_ref().unwrap().send(job).unwrap();
+    /// Like `execute_async`, but gives up instead of waiting when the queue is full.
+    pub fn try_execute_async<F>(&self, f: F) -> bool
+    where
+        F: FnOnce() -> Pin<Box<dyn Future<Output = Result<(), reqwest::Error>> + Send>>
+            + Send
+            + 'static,
+    {
+        self.try_send(Job::Async(Box::new(f)))
     }
+    /// Number of threads in the pool.
+    pub fn size(&self) -> usize {
+        self.workers.lock().unwrap().len()
+    }
+
+    /// Jobs running on a worker plus the tasks they spawned that haven't finished. Jobs abandoned
+    /// by `shutdown_timeout` no longer count.
     pub fn working_num(&self) -> u32 {
-        self.workers
-            .iter()
-            .filter(|w| *w.is_working.read().unwrap())
-            .count() as u32
+        if self.workers.lock().unwrap().is_empty() {
+            return 0;
+        }
+        self.working.get() as u32
+    }
+
+    /// Counter for work a job hands off to the runtime with `tokio::spawn`, so `working_num`
+    /// still sees it after the job's own future returns or while it waits on the task.
+    pub fn work_counter(&self) -> WorkCounter {
+        self.working.clone()
+    }
+
+    /// Stop accepting jobs and wait up to `timeout` for the queued and running ones to finish.
+    ///
+    /// Returns how many jobs were abandoned. Their workers are detached, so a download stuck on the
+    /// network can't keep the process alive.
+    pub fn shutdown_timeout(&self, timeout: Duration) -> usize {
+        drop(self.sender.lock().unwrap().take());
+        let deadline = Instant::now() + timeout;
+        let mut workers = self.workers.lock().unwrap();
+        while workers.iter().any(|w| !w.thread.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        for worker in workers.drain(..) {
+            if worker.thread.is_finished() {
+                worker.thread.join().unwrap();
+            } else {
+                debug!("Abandoning worker {}", worker.id);
+            }
+        }
+        self.pending.load(Ordering::SeqCst)
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        drop(self.sender.take());
+        drop(self.sender.get_mut().unwrap().take());
 
-        for worker in self.workers.drain(..) {
+        for worker in self.workers.get_mut().unwrap().drain(..) {
             debug!("Shutting down worker {}", worker.id);
 
             worker.thread.join().unwrap();
@@ -100,26 +242,25 @@ impl Drop for ThreadPool {
 struct Worker {
     id: usize,
     thread: thread::JoinHandle<()>,
-    is_working: Arc<RwLock<bool>>,
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let rt = Runtime::new().unwrap();
-        let is_working = Arc::new(RwLock::new(false));
-        let _is_working = Arc::clone(&is_working);
-
+    fn new(
+        id: usize,
+        receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+        rt: Arc<Runtime>,
+        pending: Arc<AtomicUsize>,
+        working: WorkCounter,
+    ) -> Worker {
         let thread = thread::spawn(move || loop {
             let message = receiver.lock().unwrap().recv();
-            {
-                let mut working = _is_working.write().unwrap();
-                *working = true;
-            }
             match message {
                 Ok(job) => {
+                    let _working = working.start();
                     debug!("Worker {id} got a job; executing.");
 
-                    match job {
+                    // A panicking job must not take the worker down with it.
+                    let ret = panic::catch_unwind(AssertUnwindSafe(|| match job {
                         Job::Sync(f) => f(),
                         Job::Async(f) => {
                             let fut = f();
@@ -131,23 +272,129 @@ impl Worker {
                                 Err(e) => debug!("Worker {id} failed async job: {e}"),
                             }
                         }
+                    }));
+                    if let Err(payload) = ret {
+                        error!("Worker {id} job panicked: {}", panic_message(&*payload));
                     }
+                    pending.fetch_sub(1, Ordering::SeqCst);
                 }
                 Err(_) => {
                     debug!("Worker {id} disconnected; shutting down.");
                     break;
                 }
             }
-            {
-                let mut working = _is_working.write().unwrap();
-                *working = false;
-            }
         });
 
-        Worker {
-            id,
-            thread,
-            is_working,
+        Worker { id, thread }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shutdown_timeout_abandons_stuck_job() {
+        let pool = ThreadPool::new(2);
+        pool.execute(|| thread::sleep(Duration::from_secs(60)));
+        pool.execute(|| {});
+        thread::sleep(Duration::from_millis(100));
+        let started = Instant::now();
+        assert_eq!(pool.shutdown_timeout(Duration::from_millis(200)), 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(pool.working_num(), 0);
+    }
+
+    #[test]
+    fn test_worker_survives_panicking_job() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(|| panic!("bad job"));
+        pool.execute(move || sender.send(()).unwrap());
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_worker_survives_panicking_future() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+        fn bad_future() -> Result<(), reqwest::Error> {
+            panic!("bad future")
+        }
+        pool.execute_async(|| Box::pin(async { bad_future() }));
+        pool.execute(move || sender.send(()).unwrap());
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        // The worker is idle again rather than stuck as working.
+        let started = Instant::now();
+        while pool.working_num() > 0 && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(pool.working_num(), 0);
+        assert_eq!(pool.shutdown_timeout(Duration::from_secs(5)), 0);
+    }
+
+    #[test]
+    fn test_working_num_counts_spawned_tasks() {
+        let pool = ThreadPool::new(1);
+        let work_counter = pool.work_counter();
+        let (sender, receiver) = mpsc::channel::<()>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        pool.execute_async(move || {
+            Box::pin(async move {
+                let working = work_counter.start();
+                tokio::spawn(async move {
+                    let _working = working;
+                    tokio::task::spawn_blocking(move || {
+                        let _ = receiver
+                            .lock()
+                            .unwrap()
+                            .recv_timeout(Duration::from_secs(5));
+                    })
+                    .await
+                    .unwrap();
+                });
+                Ok(())
+            })
+        });
+        // The job returned right away but its task still runs.
+        let started = Instant::now();
+        while pool.pending.load(Ordering::SeqCst) > 0 && started.elapsed() < Duration::from_secs(5)
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(pool.working_num(), 1);
+        sender.send(()).unwrap();
+        let started = Instant::now();
+        while pool.working_num() > 0 && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(pool.working_num(), 0);
+    }
+
+    #[test]
+    fn test_try_execute_on_full_queue() {
+        let pool = ThreadPool::with_queue_capacity(1, 1);
+        let (sender, receiver) = mpsc::channel::<()>();
+        pool.execute(move || {
+            let _ = receiver.recv_timeout(Duration::from_secs(5));
+        });
+        // Wait for the worker to take the blocking job so the queue is empty.
+        while pool.working_num() == 0 {
+            thread::sleep(Duration::from_millis(1));
         }
+        assert!(pool.try_execute(|| {}));
+        assert!(!pool.try_execute(|| {}));
+        sender.send(()).unwrap();
     }
 }
@@ -1,7 +1,22 @@
 mod access;
+mod autoindex;
+mod chaos;
 mod dir_tree;
+mod http;
+mod segment;
+mod size;
+mod throttle;
 mod timeout;
 
 pub use access::{access_flags_to_string, create_disposition_to_string};
+pub use autoindex::parse_autoindex;
+pub use chaos::FaultInjector;
 pub use dir_tree::DirTree;
-pub use timeout::wait_with_timeout;
+pub use http::{
+    content_length, content_range, content_type, etag, extension_for_type, last_modified,
+    parse_header,
+};
+pub use segment::{encode_path, encode_segment, join_dir};
+pub use size::parse_size;
+pub use throttle::RateLimiter;
+pub use timeout::{wait_with_backoff, wait_with_progress, wait_with_timeout, Progress};
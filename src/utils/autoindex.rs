@@ -0,0 +1,93 @@
+use percent_encoding::percent_decode_str;
+
+/// An entry of a directory listing page generated by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// Collect the entries linked from an autoindex page (nginx, Apache, `python -m http.server`).
+///
+/// Only relative links to direct children count, which skips the parent directory, the column sort
+/// links and anything pointing elsewhere. Directories are the links with a trailing slash.
+pub fn parse_autoindex(html: &str) -> Vec<IndexEntry> {
+    // ASCII lowercasing keeps byte offsets, so matches in `lower` index into `html` too.
+    let lower = html.to_ascii_lowercase();
+    let mut entries: Vec<IndexEntry> = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = lower[pos..].find("href=") {
+        let start = pos + found + "href=".len();
+        let rest = &html[start..];
+        let (href, len) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => match rest[1..].find(quote) {
+                Some(end) => (&rest[1..end + 1], end + 2),
+                None => break,
+            },
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+        pos = start + len;
+        if let Some(entry) = parse_href(href) {
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+fn parse_href(href: &str) -> Option<IndexEntry> {
+    let href = href.split(['?', '#']).next()?;
+    let href = href.strip_prefix("./").unwrap_or(href);
+    if href.is_empty() || href.starts_with('/') || href.contains(':') {
+        return None;
+    }
+    let (name, is_dir) = match href.strip_suffix('/') {
+        Some(name) => (name, true),
+        None => (href, false),
+    };
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    // Checked once decoded, `%2E%2E` is the parent directory as well.
+    let name = percent_decode_str(name).decode_utf8().ok()?.into_owned();
+    if name == "." || name == ".." {
+        return None;
+    }
+    Some(IndexEntry { name, is_dir })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_autoindex() {
+        let html = r#"<html><body><h1>Index of /pub/</h1>
+<a href="?C=N;O=D">Name</a> <a href="../">Parent Directory</a>
+<A HREF="docs/">docs/</A>
+<a href="read%20me.txt"><img src="/icons/text.gif"></a> <a href="read%20me.txt">read me.txt</a>
+<a href='./logo.png'>logo.png</a> <a href=data.bin>data.bin</a>
+<a href="https://example.com/">elsewhere</a> <a href="/absolute">abs</a>
+<a href="%2E%2E/">up</a> <a href="%2e">here</a>
+</body></html>"#;
+        let entry = |name: &str, is_dir| IndexEntry {
+            name: name.to_string(),
+            is_dir,
+        };
+        assert_eq!(
+            parse_autoindex(html),
+            vec![
+                entry("docs", true),
+                entry("read me.txt", false),
+                entry("logo.png", false),
+                entry("data.bin", false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Artificial latency and failures for exercising consumers of the mount.
+#[derive(Debug)]
+pub struct FaultInjector {
+    pub latency: Duration,
+    pub failure_rate: f64,
+    state: AtomicU64,
+}
+
+impl FaultInjector {
+    pub fn new(latency: Duration, failure_rate: f64, seed: u64) -> Self {
+        Self {
+            latency,
+            failure_rate,
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// splitmix64, so the same seed yields the same sequence of failures.
+    fn next_f64(&self) -> f64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn should_fail(&self) -> bool {
+        self.failure_rate > 0.0 && self.next_f64() < self.failure_rate
+    }
+
+    pub fn delay(&self) {
+        if !self.latency.is_zero() {
+            std::thread::sleep(self.latency);
+        }
+    }
+}
+
+#[test]
+fn test_fault_injector_rate() {
+    let never = FaultInjector::new(Duration::ZERO, 0.0, 1);
+    assert!((0..1000).all(|_| !never.should_fail()));
+    let always = FaultInjector::new(Duration::ZERO, 1.0, 1);
+    assert!((0..1000).all(|_| always.should_fail()));
+
+    let some = FaultInjector::new(Duration::ZERO, 0.1, 42);
+    let failures = (0..10000).filter(|_| some.should_fail()).count();
+    assert!((800..1200).contains(&failures), "failures = {failures}");
+
+    let replay = FaultInjector::new(Duration::ZERO, 0.1, 42);
+    let again = FaultInjector::new(Duration::ZERO, 0.1, 42);
+    assert!((0..100).all(|_| replay.should_fail() == again.should_fail()));
+}
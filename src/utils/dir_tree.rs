@@ -1,13 +1,113 @@
+use std::collections::HashSet;
+
 use serde::Deserialize;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct DirTree {
     pub name: String,
+    #[serde(default)]
     pub children: Vec<DirTree>,
+    /// File size in bytes, lets listings show it before anything is downloaded.
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Modification time of the entry in seconds since the Unix epoch.
+    #[serde(default)]
+    pub mtime: Option<u64>,
+    /// Where the file is downloaded from, absolute or relative to `--url`. Defaults to its path.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Expected SHA-256 of the file as hex, a download that doesn't match can't be read.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Tree path of the directory this one is an alias of, like `v2.3/`. Opening it opens the
+    /// target, its own children are ignored.
+    #[serde(default)]
+    pub link: Option<String>,
 }
 
 impl DirTree {
     pub fn is_folder(&self) -> bool {
         self.name.ends_with('/')
     }
+
+    /// Check the invariants `build_tree` relies on, the error names the first offending entry.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut stack = vec![(String::new(), self)];
+        while let Some((path, dir)) = stack.pop() {
+            let mut names = HashSet::new();
+            for child in dir.children.iter() {
+                let name = child.name.strip_suffix('/').unwrap_or(&child.name);
+                let child_path = format!("/{path}{}", child.name);
+                if name.is_empty() {
+                    return Err(format!("/{path}: an entry has an empty name"));
+                }
+                if name.contains(['/', '\\']) || name == "." || name == ".." {
+                    return Err(format!("{child_path}: {name:?} isn't a valid entry name"));
+                }
+                if !names.insert(name) {
+                    return Err(format!("{child_path}: declared more than once"));
+                }
+                if !child.is_folder() && !child.children.is_empty() {
+                    return Err(format!(
+                        "{child_path}: has children but isn't a folder, folder names end with `/`"
+                    ));
+                }
+                if !child.is_folder() && child.link.is_some() {
+                    return Err(format!("{child_path}: only folders can be links"));
+                }
+                if child.is_folder() {
+                    stack.push((format!("{path}{}", child.name), child));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let validate = |json: &str| serde_json::from_str::<DirTree>(json).unwrap().validate();
+        assert_eq!(
+            validate(
+                r#"{"name": "/", "children": [
+                    {"name": "a.txt"},
+                    {"name": "docs/", "children": [{"name": "a.txt"}]},
+                    {"name": "latest/", "link": "docs/"}
+                ]}"#
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            validate(
+                r#"{"name": "/", "children": [{"name": "docs/", "children": [{"name": ""}]}]}"#
+            ),
+            Err("/docs/: an entry has an empty name".to_string())
+        );
+        assert_eq!(
+            validate(r#"{"name": "/", "children": [{"name": "a/b.txt"}]}"#),
+            Err("/a/b.txt: \"a/b.txt\" isn't a valid entry name".to_string())
+        );
+        assert_eq!(
+            validate(r#"{"name": "/", "children": [{"name": "../"}]}"#),
+            Err("/../: \"..\" isn't a valid entry name".to_string())
+        );
+        assert_eq!(
+            validate(r#"{"name": "/", "children": [{"name": "docs/"}, {"name": "docs"}]}"#),
+            Err("/docs: declared more than once".to_string())
+        );
+        assert_eq!(
+            validate(
+                r#"{"name": "/", "children": [{"name": "docs", "children": [{"name": "a.txt"}]}]}"#
+            ),
+            Err("/docs: has children but isn't a folder, folder names end with `/`".to_string())
+        );
+        assert_eq!(
+            validate(r#"{"name": "/", "children": [{"name": "latest", "link": "docs/"}]}"#),
+            Err("/latest: only folders can be links".to_string())
+        );
+    }
 }
@@ -0,0 +1,139 @@
+use std::time::SystemTime;
+
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    LAST_MODIFIED,
+};
+
+/// Parse a `Name: Value` command line header.
+pub fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("`{s}` is not in `Name: Value` form"))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|e| format!("invalid header name `{}`: {e}", name.trim()))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|e| format!("invalid value for header `{name}`: {e}"))?;
+    Ok((name, value))
+}
+
+/// The `Last-Modified` response header as a timestamp, if present and well-formed.
+pub fn last_modified(headers: &HeaderMap) -> Option<SystemTime> {
+    let value = headers.get(LAST_MODIFIED)?.to_str().ok()?;
+    httpdate::parse_http_date(value).ok()
+}
+
+/// The `Content-Length` header, read directly since a HEAD response has no body to size.
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The `Content-Range` header of a 206 as `(first, last, complete length)`, the length is `None`
+/// when the server sent `*`.
+pub fn content_range(headers: &HeaderMap) -> Option<(u64, u64, Option<u64>)> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((first.trim().parse().ok()?, last.trim().parse().ok()?, total))
+}
+
+/// The `ETag` header, validators included.
+pub fn etag(headers: &HeaderMap) -> Option<String> {
+    Some(headers.get(ETAG)?.to_str().ok()?.to_string())
+}
+
+/// The `Content-Type` header, parameters included.
+pub fn content_type(headers: &HeaderMap) -> Option<&str> {
+    headers.get(CONTENT_TYPE)?.to_str().ok()
+}
+
+/// The usual file extension for a content type, ignoring generic binary types.
+pub fn extension_for_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    Some(match mime.as_str() {
+        "text/html" => "html",
+        "text/plain" => "txt",
+        "text/css" => "css",
+        "text/csv" => "csv",
+        "text/xml" | "application/xml" => "xml",
+        "text/javascript" | "application/javascript" => "js",
+        "application/json" => "json",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "application/gzip" => "gz",
+        "application/wasm" => "wasm",
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        "image/x-icon" | "image/vnd.microsoft.icon" => "ico",
+        "audio/mpeg" => "mp3",
+        "audio/ogg" => "ogg",
+        "audio/wav" => "wav",
+        "video/mp4" => "mp4",
+        "video/webm" => "webm",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let (name, value) = parse_header("Authorization: Bearer abc:def").unwrap();
+        assert_eq!(name, "authorization");
+        assert_eq!(value, "Bearer abc:def");
+        assert!(parse_header("Cookie").is_err());
+        assert!(parse_header("Bad Name: x").is_err());
+        assert!(parse_header("X-Test: line\nbreak").is_err());
+    }
+
+    #[test]
+    fn test_last_modified() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(last_modified(&headers), None);
+        headers.insert(LAST_MODIFIED, "not a date".parse().unwrap());
+        assert_eq!(last_modified(&headers), None);
+        headers.insert(
+            LAST_MODIFIED,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(
+            last_modified(&headers),
+            Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1445412480))
+        );
+    }
+
+    #[test]
+    fn test_content_range() {
+        let range = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_RANGE, value.parse().unwrap());
+            content_range(&headers)
+        };
+        assert_eq!(range("bytes 0-99/1000"), Some((0, 99, Some(1000))));
+        assert_eq!(range("bytes 100-199/*"), Some((100, 199, None)));
+        assert_eq!(range("bytes */1000"), None);
+        assert_eq!(content_range(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_extension_for_type() {
+        assert_eq!(extension_for_type("Image/JPEG"), Some("jpg"));
+        assert_eq!(extension_for_type("text/html; charset=utf-8"), Some("html"));
+        assert_eq!(extension_for_type("application/octet-stream"), None);
+    }
+}
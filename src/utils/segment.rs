@@ -0,0 +1,84 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use url::Url;
+
+/// Characters escaped when a file name becomes a url path segment.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Percent-encode one file or directory name for use as a url path segment.
+pub fn encode_segment(name: &str) -> String {
+    utf8_percent_encode(name, SEGMENT).to_string()
+}
+
+/// Turn a path of the mount, separated by `\` or `/`, into a relative url path with every
+/// component encoded on its own. Empty components are dropped.
+pub fn encode_path(path: &str) -> String {
+    path.split(['\\', '/'])
+        .filter(|s| !s.is_empty())
+        .map(encode_segment)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `base` taken as a directory with the components of `dir` appended, encoded like
+/// `encode_path`. The result ends with a slash so relative joins onto it keep every segment.
+pub fn join_dir(base: &Url, dir: &str) -> Url {
+    let mut path = base.path().trim_end_matches('/').to_string();
+    for component in dir.split(['\\', '/']).filter(|s| !s.is_empty()) {
+        path.push('/');
+        path.push_str(&encode_segment(component));
+    }
+    path.push('/');
+    let mut url = base.clone();
+    url.set_path(&path);
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(encode_path("\\my file.txt"), "my%20file.txt");
+        assert_eq!(encode_path("\\a\\b#1\\what?.txt"), "a/b%231/what%3F.txt");
+        assert_eq!(encode_path("\\100%.txt"), "100%25.txt");
+        assert_eq!(
+            encode_path("\\文档\\報告 1.pdf"),
+            "%E6%96%87%E6%A1%A3/%E5%A0%B1%E5%91%8A%201.pdf"
+        );
+        assert_eq!(encode_path("\\a\\\\b\\"), "a/b");
+        assert_eq!(encode_path(""), "");
+    }
+
+    #[test]
+    fn test_join_dir() {
+        let join = |base: &str, dir: &str| join_dir(&Url::parse(base).unwrap(), dir).to_string();
+        assert_eq!(join("https://host/", "/files/"), "https://host/files/");
+        assert_eq!(join("https://host", "files"), "https://host/files/");
+        assert_eq!(join("https://host/app", "files"), "https://host/app/files/");
+        assert_eq!(join("https://host/app/", "/a//b/"), "https://host/app/a/b/");
+        assert_eq!(
+            join("https://host/app/", "my files"),
+            "https://host/app/my%20files/"
+        );
+        assert_eq!(join("https://host/app", ""), "https://host/app/");
+        assert_eq!(
+            join_dir(&Url::parse("https://host/app").unwrap(), "files")
+                .join("./a.txt")
+                .unwrap()
+                .as_str(),
+            "https://host/app/files/a.txt"
+        );
+    }
+}
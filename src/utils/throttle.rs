@@ -0,0 +1,42 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Token bucket capping the combined rate of every download, see `--max-bps`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    /// When the bytes let through so far will have drained from the bucket.
+    drained_at: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Up to a second worth of bytes can pass at once after the downloads were idle.
+    const BURST: Duration = Duration::from_secs(1);
+
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            drained_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Account for `len` received bytes and return how long to pause to stay under the rate.
+    pub fn reserve(&self, len: usize) -> Duration {
+        let now = Instant::now();
+        let mut drained_at = self.drained_at.lock().unwrap();
+        *drained_at = (*drained_at).max(now)
+            + Duration::from_secs_f64(len as f64 / self.bytes_per_sec as f64);
+        drained_at.saturating_duration_since(now + Self::BURST)
+    }
+}
+
+#[test]
+fn test_rate_limiter_reserve() {
+    let limiter = RateLimiter::new(1000);
+    // The burst allowance covers the first second.
+    assert_eq!(limiter.reserve(500), Duration::ZERO);
+    let wait = limiter.reserve(1500);
+    assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+}
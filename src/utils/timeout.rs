@@ -1,3 +1,8 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
 use winapi::shared::{ntdef::NTSTATUS, ntstatus::STATUS_IO_TIMEOUT};
 
 pub fn wait_with_timeout<F, E>(
@@ -23,3 +28,238 @@ where
     }
     Ok(timeout)
 }
+
+/// Signalled by a download whenever it makes progress, so waiting readers wake up right away
+/// instead of at their next poll.
+#[derive(Debug, Default)]
+pub struct Progress {
+    events: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Progress {
+    pub fn notify(&self) {
+        *self.events.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    /// Number of notifications so far, pass it to `wait` to not miss one sent in between.
+    pub fn events(&self) -> u64 {
+        *self.events.lock().unwrap()
+    }
+
+    /// Sleep until there are more than `seen` notifications or `timeout` passes.
+    pub fn wait(&self, seen: u64, timeout: Duration) {
+        let events = self.events.lock().unwrap();
+        let _ = self
+            .changed
+            .wait_timeout_while(events, timeout, |events| *events == seen)
+            .unwrap();
+    }
+}
+
+/// Like `wait_with_timeout`, but the deadline starts over whenever `progress` changes, so a slow but
+/// steady download isn't given up on. With `wake` the wait ends as soon as it is notified, polling
+/// every `delay_ms` remains the fallback.
+pub fn wait_with_progress<F, P, E>(
+    should_continue: F,
+    progress: P,
+    wake: Option<&Progress>,
+    timeout: i64,
+    delay_ms: u64,
+    on_timeout: Option<E>,
+) -> Result<i64, NTSTATUS>
+where
+    F: FnMut() -> bool,
+    P: FnMut() -> u64,
+    E: FnOnce() -> Result<i64, NTSTATUS>,
+{
+    wait_with_backoff(
+        should_continue,
+        progress,
+        wake,
+        timeout,
+        delay_ms,
+        delay_ms,
+        on_timeout,
+    )
+}
+
+/// Like `wait_with_progress`, but the poll interval doubles from `delay_ms` up to `max_delay_ms`
+/// while nothing changes, and starts over at `delay_ms` when `progress` does. For waits many
+/// handles do at once, where polling every `delay_ms` adds up.
+pub fn wait_with_backoff<F, P, E>(
+    mut should_continue: F,
+    mut progress: P,
+    wake: Option<&Progress>,
+    timeout: i64,
+    delay_ms: u64,
+    max_delay_ms: u64,
+    on_timeout: Option<E>,
+) -> Result<i64, NTSTATUS>
+where
+    F: FnMut() -> bool,
+    P: FnMut() -> u64,
+    E: FnOnce() -> Result<i64, NTSTATUS>,
+{
+    let timeout = Duration::from_millis(timeout.max(0) as u64);
+    let min_delay = Duration::from_millis(delay_ms);
+    let max_delay = Duration::from_millis(max_delay_ms).max(min_delay);
+    let mut delay = min_delay;
+    let mut last = progress();
+    let mut deadline = Instant::now().checked_add(timeout);
+    loop {
+        let seen = wake.map(Progress::events);
+        if !should_continue() {
+            let remaining = deadline.map_or(Duration::MAX, |d| d - Instant::now().min(d));
+            return Ok(remaining.as_millis().min(i64::MAX as u128) as i64);
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            if let Some(on_timeout) = on_timeout {
+                return on_timeout();
+            } else {
+                return Err(STATUS_IO_TIMEOUT);
+            }
+        }
+        match (wake, seen) {
+            (Some(wake), Some(seen)) => wake.wait(seen, delay),
+            _ => std::thread::sleep(delay),
+        }
+        let now = progress();
+        if now != last {
+            last = now;
+            deadline = Instant::now().checked_add(timeout);
+            delay = min_delay;
+        } else {
+            delay = delay.saturating_mul(2).min(max_delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_wait_with_progress_resets_deadline() {
+        // Three times the timeout in total, but something arrives about every 20ms.
+        let polls = std::cell::Cell::new(0);
+        let waited = wait_with_progress(
+            || polls.get() < 30,
+            || {
+                polls.set(polls.get() + 1);
+                polls.get() / 2
+            },
+            None,
+            100,
+            10,
+            None::<fn() -> Result<i64, NTSTATUS>>,
+        );
+        assert!(waited.is_ok());
+        let stalled = wait_with_progress(
+            || true,
+            || 0,
+            None,
+            30,
+            10,
+            None::<fn() -> Result<i64, NTSTATUS>>,
+        );
+        assert_eq!(stalled, Err(STATUS_IO_TIMEOUT));
+    }
+
+    #[test]
+    fn test_wait_with_backoff() {
+        // Nothing happens for 1s: 10, 20, 40, 80, 160 and then 200ms apart.
+        let polls = std::cell::Cell::new(0);
+        let started = Instant::now();
+        let waited = wait_with_backoff(
+            || {
+                polls.set(polls.get() + 1);
+                started.elapsed() < Duration::from_secs(1)
+            },
+            || 0,
+            None,
+            60000,
+            10,
+            200,
+            None::<fn() -> Result<i64, NTSTATUS>>,
+        );
+        assert!(waited.is_ok());
+        assert!(polls.get() <= 12, "polled {} times", polls.get());
+
+        // Stops at the first poll that sees the condition met.
+        let done = std::cell::Cell::new(false);
+        let polls = std::cell::Cell::new(0);
+        let waited = wait_with_backoff(
+            || {
+                polls.set(polls.get() + 1);
+                done.set(polls.get() > 3);
+                !done.get()
+            },
+            || 0,
+            None,
+            60000,
+            10,
+            200,
+            None::<fn() -> Result<i64, NTSTATUS>>,
+        );
+        assert!(waited.is_ok());
+        assert_eq!(polls.get(), 4);
+
+        // Progress brings the interval back down, a steady trickle keeps being polled quickly.
+        let polls = std::cell::Cell::new(0);
+        let started = Instant::now();
+        wait_with_backoff(
+            || {
+                polls.set(polls.get() + 1);
+                started.elapsed() < Duration::from_millis(500)
+            },
+            || polls.get(),
+            None,
+            60000,
+            10,
+            200,
+            None::<fn() -> Result<i64, NTSTATUS>>,
+        )
+        .unwrap();
+        assert!(polls.get() > 20, "polled {} times", polls.get());
+
+        let stalled = wait_with_backoff(
+            || true,
+            || 0,
+            None,
+            100,
+            10,
+            200,
+            None::<fn() -> Result<i64, NTSTATUS>>,
+        );
+        assert_eq!(stalled, Err(STATUS_IO_TIMEOUT));
+    }
+
+    #[test]
+    fn test_wait_with_progress_wakes_on_notify() {
+        let wake = Arc::new(Progress::default());
+        let done = Arc::new(AtomicBool::new(false));
+        let (_wake, _done) = (Arc::clone(&wake), Arc::clone(&done));
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            _done.store(true, Ordering::Release);
+            _wake.notify();
+        });
+        let started = Instant::now();
+        let waited = wait_with_progress(
+            || !done.load(Ordering::Acquire),
+            || 0,
+            Some(&wake),
+            60000,
+            30000,
+            None::<fn() -> Result<i64, NTSTATUS>>,
+        );
+        assert!(waited.is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use log::debug;
+use reqwest::Client;
+use url::Url;
+
+use crate::utils::{join_dir, parse_autoindex, DirTree};
+
+/// Compare the declared tree with the server's directory listings.
+///
+/// Returns one line per discrepancy, an empty list means the server has exactly the declared entries.
+pub async fn verify_structure(client: &Client, url: &Url, dir_tree: &DirTree) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut stack = vec![(String::new(), dir_tree)];
+    while let Some((path, dir)) = stack.pop() {
+        let listing_url = join_dir(url, &path);
+        debug!("verify: listing {listing_url}");
+        let listing = match fetch_listing(client, listing_url).await {
+            Ok(listing) => listing,
+            Err(e) => {
+                problems.push(format!("/{path}: could not list: {e}"));
+                continue;
+            }
+        };
+        let mut on_server = parse_autoindex(&listing)
+            .into_iter()
+            .map(|entry| (entry.name, entry.is_dir))
+            .collect::<HashMap<_, _>>();
+        for child in dir.children.iter() {
+            let name = child.name.trim_end_matches('/');
+            match on_server.remove(name) {
+                None => problems.push(format!(
+                    "/{path}{}: declared but not on the server",
+                    child.name
+                )),
+                Some(is_dir) if is_dir != child.is_folder() => problems.push(format!(
+                    "/{path}{name}: declared as a {} but the server has a {}",
+                    kind(child.is_folder()),
+                    kind(is_dir)
+                )),
+                Some(true) => stack.push((format!("{path}{name}/"), child)),
+                Some(false) => (),
+            }
+        }
+        let mut undeclared = on_server.into_iter().collect::<Vec<_>>();
+        undeclared.sort();
+        for (name, is_dir) in undeclared {
+            let slash = if is_dir { "/" } else { "" };
+            problems.push(format!(
+                "/{path}{name}{slash}: on the server but not declared"
+            ));
+        }
+    }
+    problems
+}
+
+async fn fetch_listing(client: &Client, url: Url) -> Result<String, reqwest::Error> {
+    client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+fn kind(is_dir: bool) -> &'static str {
+    if is_dir {
+        "directory"
+    } else {
+        "file"
+    }
+}